@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_adofai::Level;
+
+// Loading and parsing must never panic, whatever the input.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut level) = Level::from_bytes(data) {
+        let _ = level.parse();
+    }
+});
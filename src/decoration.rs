@@ -0,0 +1,483 @@
+use crate::*;
+use getset::*;
+use rgb::Rgba;
+use serde::{Deserialize, Serialize};
+use serde_json_lenient::{Map, Value};
+use vector2d::Vector2D;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum RelativeToDecoration {
+    #[default]
+    Tile,
+    Global,
+    RedPlanet,
+    BluePlanet,
+    GreenPlanet,
+    Camera,
+    CameraAspect,
+    LastPosition,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum Hitbox {
+    #[default]
+    None,
+    Kill,
+    Event,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum FailHitboxType {
+    #[default]
+    Box,
+    Circle,
+    Capsule,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum ObjectType {
+    #[default]
+    Floor,
+    Planet,
+}
+
+/// An image decoration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDecoration {
+    /// The tile the decoration is placed on, present in levels that attach it to one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<usize>,
+    #[serde(default)]
+    pub decoration_image: String,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub position: Vector2D<f64>,
+    #[serde(default)]
+    pub relative_to: RelativeToDecoration,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub pivot_offset: Vector2D<f64>,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_rotation: bool,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub scale: Vector2D<f64>,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_scale: bool,
+    /// How many times the image is repeated along each axis.
+    #[serde(
+        default = "vector2d_1",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub tile: Vector2D<f64>,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub color: Rgba<u8>,
+    #[serde(default = "f64_100")]
+    pub opacity: f64,
+    #[serde(default = "f64_minus_1")]
+    pub depth: f64,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax_offset: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub tag: Vec<String>,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub image_smoothing: bool,
+    #[serde(default)]
+    pub hitbox: Hitbox,
+    #[serde(default)]
+    pub hitbox_event_tag: String,
+    #[serde(default)]
+    pub fail_hitbox_type: FailHitboxType,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub fail_hitbox_scale: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub fail_hitbox_offset: Vector2D<f64>,
+    #[serde(default)]
+    pub fail_hitbox_rotation: f64,
+    /// Keys not modelled above, written back on save.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A text decoration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddText {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<usize>,
+    #[serde(default)]
+    pub dec_text: String,
+    #[serde(default = "font_default")]
+    pub font: String,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub position: Vector2D<f64>,
+    #[serde(default)]
+    pub relative_to: RelativeToDecoration,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub pivot_offset: Vector2D<f64>,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_rotation: bool,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub scale: Vector2D<f64>,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_scale: bool,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub color: Rgba<u8>,
+    #[serde(default = "f64_100")]
+    pub opacity: f64,
+    #[serde(default = "f64_minus_1")]
+    pub depth: f64,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax_offset: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub tag: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A floor or planet placed as a decoration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddObject {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<usize>,
+    #[serde(default)]
+    pub object_type: ObjectType,
+    #[serde(
+        default = "rgba_ff0000",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub planet_color: Rgba<u8>,
+    #[serde(
+        default = "rgba_ff0000",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub planet_tail_color: Rgba<u8>,
+    #[serde(default = "f64_180")]
+    pub track_angle: f64,
+    #[serde(
+        default = "rgba_debb7b",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub track_color: Rgba<u8>,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub secondary_track_color: Rgba<u8>,
+    #[serde(default = "f64_100")]
+    pub track_opacity: f64,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub position: Vector2D<f64>,
+    #[serde(default)]
+    pub relative_to: RelativeToDecoration,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub pivot_offset: Vector2D<f64>,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_rotation: bool,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub scale: Vector2D<f64>,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_scale: bool,
+    #[serde(default = "f64_minus_1")]
+    pub depth: f64,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax_offset: Vector2D<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub tag: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "eventType")]
+pub enum Decorations {
+    AddDecoration(AddDecoration),
+    AddText(AddText),
+    AddObject(AddObject),
+}
+impl Decorations {
+    pub fn floor(&self) -> Option<usize> {
+        match self {
+            Self::AddDecoration(decoration) => decoration.floor,
+            Self::AddText(decoration) => decoration.floor,
+            Self::AddObject(decoration) => decoration.floor,
+        }
+    }
+    pub fn tag(&self) -> &Vec<String> {
+        match self {
+            Self::AddDecoration(decoration) => &decoration.tag,
+            Self::AddText(decoration) => &decoration.tag,
+            Self::AddObject(decoration) => &decoration.tag,
+        }
+    }
+    pub fn tag_mut(&mut self) -> &mut Vec<String> {
+        match self {
+            Self::AddDecoration(decoration) => &mut decoration.tag,
+            Self::AddText(decoration) => &mut decoration.tag,
+            Self::AddObject(decoration) => &mut decoration.tag,
+        }
+    }
+    /// The state before any event, with the values this kind of decoration does not have left
+    /// empty.
+    pub(crate) fn initial_state(&self) -> DecorationState {
+        let mut data = DecorationState::default();
+        let (position, pivot_offset, rotation, scale, depth, parallax) = match self {
+            Self::AddDecoration(decoration) => {
+                data.image.orig = Some(decoration.decoration_image.clone());
+                data.color.orig = Some(decoration.color);
+                data.opacity.orig = Some(decoration.opacity);
+                (
+                    decoration.position,
+                    decoration.pivot_offset,
+                    decoration.rotation,
+                    decoration.scale,
+                    decoration.depth,
+                    decoration.parallax,
+                )
+            }
+            Self::AddText(decoration) => {
+                data.text.orig = Some(decoration.dec_text.clone());
+                data.color.orig = Some(decoration.color);
+                data.opacity.orig = Some(decoration.opacity);
+                (
+                    decoration.position,
+                    decoration.pivot_offset,
+                    decoration.rotation,
+                    decoration.scale,
+                    decoration.depth,
+                    decoration.parallax,
+                )
+            }
+            Self::AddObject(decoration) => {
+                data.planet_color.orig = Some(decoration.planet_color);
+                data.planet_tail_color.orig = Some(decoration.planet_tail_color);
+                data.track_angle.orig = Some(decoration.track_angle);
+                data.track_color.orig = Some(decoration.track_color);
+                data.secondary_track_color.orig = Some(decoration.secondary_track_color);
+                data.track_opacity.orig = Some(decoration.track_opacity);
+                (
+                    decoration.position,
+                    decoration.pivot_offset,
+                    decoration.rotation,
+                    decoration.scale,
+                    decoration.depth,
+                    decoration.parallax,
+                )
+            }
+        };
+        data.position.orig = Some(position);
+        data.pivot_offset.orig = Some(pivot_offset);
+        data.rotation.orig = Some(rotation);
+        data.scale.orig = Some(scale);
+        data.depth.orig = Some(depth);
+        data.parallax.orig = Some(parallax);
+        data
+    }
+}
+
+/// The values of a decoration that events change over time, computed by [`Level::parse`]
+/// (`orig`) and [`Level::update`] (`now`).
+#[derive(Default, Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct DecorationState {
+    pub(crate) position: DynamicValue<Vector2D<f64>>,
+    pub(crate) pivot_offset: DynamicValue<Vector2D<f64>>,
+    pub(crate) rotation: DynamicValue<f64>,
+    pub(crate) scale: DynamicValue<Vector2D<f64>>,
+    pub(crate) color: DynamicValue<Rgba<u8>>,
+    pub(crate) opacity: DynamicValue<f64>,
+    pub(crate) depth: DynamicValue<f64>,
+    pub(crate) parallax: DynamicValue<Vector2D<f64>>,
+    /// The image of an AddDecoration.
+    pub(crate) image: DynamicValue<String>,
+    /// The text of an AddText.
+    pub(crate) text: DynamicValue<String>,
+
+    // AddObject
+    pub(crate) planet_color: DynamicValue<Rgba<u8>>,
+    pub(crate) planet_tail_color: DynamicValue<Rgba<u8>>,
+    pub(crate) track_angle: DynamicValue<f64>,
+    pub(crate) track_color: DynamicValue<Rgba<u8>>,
+    pub(crate) secondary_track_color: DynamicValue<Rgba<u8>>,
+    pub(crate) track_opacity: DynamicValue<f64>,
+}
+impl DecorationState {
+    pub(crate) fn orig2now(&mut self) {
+        self.position.orig2now();
+        self.pivot_offset.orig2now();
+        self.rotation.orig2now();
+        self.scale.orig2now();
+        self.color.orig2now();
+        self.opacity.orig2now();
+        self.depth.orig2now();
+        self.parallax.orig2now();
+        self.image.orig2now();
+        self.text.orig2now();
+
+        self.planet_color.orig2now();
+        self.planet_tail_color.orig2now();
+        self.track_angle.orig2now();
+        self.track_color.orig2now();
+        self.secondary_track_color.orig2now();
+        self.track_opacity.orig2now();
+    }
+}
+
+/// An item of the `decorations` array of a level.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum DecorationData {
+    Known {
+        decoration: Decorations,
+        data: DecorationState,
+    },
+    /// A decoration whose `eventType` is not modelled (or could not be read into its model).
+    /// The original JSON object is kept and written back verbatim.
+    Unknown {
+        event_type: String,
+        raw_json: Map<String, Value>,
+    },
+}
+impl DecorationData {
+    pub fn floor(&self) -> Option<usize> {
+        match self {
+            DecorationData::Known { decoration, .. } => decoration.floor(),
+            DecorationData::Unknown { raw_json, .. } => raw_json
+                .get("floor")
+                .and_then(|floor| floor.as_u64())
+                .map(|floor| floor as usize),
+        }
+    }
+}
+impl Serialize for DecorationData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            DecorationData::Known { decoration, .. } => decoration.serialize(serializer),
+            DecorationData::Unknown { raw_json, .. } => raw_json.serialize(serializer),
+        }
+    }
+}
+
+impl Level {
+    /// The decorations with one of `tags`, with their state.
+    pub(crate) fn tagged_decorations<'a>(
+        &'a mut self,
+        tags: &'a [String],
+    ) -> impl Iterator<Item = &'a mut DecorationState> {
+        self.decorations.iter_mut().filter_map(move |decoration| match decoration {
+            DecorationData::Known { decoration, data }
+                if decoration.tag().iter().any(|tag| tags.contains(tag)) =>
+            {
+                Some(data)
+            }
+            _ => None,
+        })
+    }
+}
@@ -8,8 +8,9 @@ const C4: f64 = (2.0 * PI) / 3.0;
 const C5: f64 = (2.0 * PI) / 4.5;
 const N1: f64 = 7.5625;
 const D1: f64 = 2.75;
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
 pub enum Easing {
+    #[default]
     Linear,
     InSine,
     OutSine,
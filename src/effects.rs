@@ -0,0 +1,158 @@
+use getset::*;
+use rgb::Rgba;
+use serde::{Deserialize, Serialize};
+use vector2d::Vector2D;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Filter {
+    Grayscale,
+    Sepia,
+    Invert,
+    #[serde(rename = "VHS")]
+    Vhs,
+    #[serde(rename = "EightiesTV")]
+    EightiesTv,
+    #[serde(rename = "FiftiesTV")]
+    FiftiesTv,
+    Arcade,
+    #[serde(rename = "LED")]
+    Led,
+    Rain,
+    Blizzard,
+    PixelSnow,
+    Compression,
+    Glitch,
+    Pixelate,
+    Waves,
+    Static,
+    Grain,
+    MotionBlur,
+    Fisheye,
+    Aberration,
+    Drawing,
+    Neon,
+    Handheld,
+    NightVision,
+    Funk,
+    Tunnel,
+    Weird3D,
+    Blur,
+    BlurFocus,
+    GaussianBlur,
+    HexagonBlack,
+    Posterize,
+    Sharpen,
+    Contrast,
+    EdgeBlackLine,
+    OilPaint,
+    SuperDot,
+    WaterDrop,
+    LightWater,
+    Petals,
+    PetalsInstant,
+    /// A filter this crate does not know by name.
+    #[serde(untagged)]
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum FlashPlane {
+    Foreground,
+    #[default]
+    Background,
+}
+
+/// A filter that is on, in the order the filters were turned on.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct ActiveFilter {
+    #[getset(get = "pub")]
+    pub(crate) filter: Filter,
+    #[getset(get_copy = "pub")]
+    pub(crate) intensity: f64,
+    /// The `filterProperties` of the last SetFilterAdvanced for this filter.
+    #[getset(get = "pub")]
+    pub(crate) properties: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct BloomState {
+    pub(crate) threshold: f64,
+    pub(crate) intensity: f64,
+    pub(crate) color: Rgba<u8>,
+}
+
+/// The post-processing effects on screen, set by [`Level::update`](crate::Level::update).
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct ScreenEffects {
+    pub(crate) filters: Vec<ActiveFilter>,
+    pub(crate) bloom: Option<BloomState>,
+    /// The flash color over the level, while a flash runs or if it ends visible.
+    pub(crate) foreground_flash: Option<Rgba<u8>>,
+    /// The flash color behind the level, while a flash runs or if it ends visible.
+    pub(crate) background_flash: Option<Rgba<u8>>,
+    pub(crate) hall_of_mirrors: bool,
+    /// How many times the screen is repeated across and down.
+    pub(crate) tiling: Vector2D<f64>,
+    /// How fast the screen scrolls, in screens per second.
+    pub(crate) scroll_velocity: Vector2D<f64>,
+    /// How far the screen has scrolled, in screens.
+    pub(crate) scroll_offset: Vector2D<f64>,
+    /// The scroll offset and time of the last velocity change.
+    #[getset(skip)]
+    pub(crate) scroll_base: (Vector2D<f64>, f64),
+}
+impl Default for ScreenEffects {
+    fn default() -> Self {
+        ScreenEffects {
+            filters: vec![],
+            bloom: None,
+            foreground_flash: None,
+            background_flash: None,
+            hall_of_mirrors: false,
+            tiling: Vector2D::new(1.0, 1.0),
+            scroll_velocity: Vector2D::new(0.0, 0.0),
+            scroll_offset: Vector2D::new(0.0, 0.0),
+            scroll_base: (Vector2D::new(0.0, 0.0), 0.0),
+        }
+    }
+}
+impl ScreenEffects {
+    pub fn filter(&self, filter: &Filter) -> Option<&ActiveFilter> {
+        self.filters.iter().find(|active| active.filter == *filter)
+    }
+    /// Turns `filter` on if it is off, starting from no intensity.
+    pub(crate) fn enable_filter(&mut self, filter: &Filter) -> &mut ActiveFilter {
+        let index = match self
+            .filters
+            .iter()
+            .position(|active| active.filter == *filter)
+        {
+            Some(index) => index,
+            None => {
+                self.filters.push(ActiveFilter {
+                    filter: filter.clone(),
+                    intensity: 0.0,
+                    properties: None,
+                });
+                self.filters.len() - 1
+            }
+        };
+        &mut self.filters[index]
+    }
+    pub(crate) fn disable_filter(&mut self, filter: &Filter) {
+        self.filters.retain(|active| active.filter != *filter);
+    }
+    /// Changes the scroll velocity at `since` and scrolls on to `seconds`.
+    pub(crate) fn scroll(&mut self, velocity: Vector2D<f64>, since: f64, seconds: f64) {
+        let (offset, last_since) = self.scroll_base;
+        let offset = offset + self.scroll_velocity * (since - last_since);
+        self.scroll_base = (offset, since);
+        self.scroll_velocity = velocity;
+        self.scroll_offset = offset + velocity * (seconds - since);
+    }
+    pub(crate) fn disable_other_filters(&mut self, filter: &Filter) {
+        self.filters.retain(|active| active.filter == *filter);
+    }
+}
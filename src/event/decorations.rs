@@ -0,0 +1,428 @@
+use super::*;
+use crate::*;
+use rgb::Rgba;
+use serde::{Deserialize, Serialize};
+use std::error;
+use vector2d::Vector2D;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveDecorations {
+    pub floor: usize,
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default)]
+    pub duration: f64,
+    /// The tags of the decorations to move.
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub tag: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoration_image: Option<String>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_option_f64",
+        deserialize_with = "de_vector2d_option_f64"
+    )]
+    pub position_offset: Vector2D<Option<f64>>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_option_f64",
+        deserialize_with = "de_vector2d_option_f64"
+    )]
+    pub pivot_offset: Vector2D<Option<f64>>,
+    #[serde(default)]
+    pub rotation_offset: Option<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_option_f64",
+        deserialize_with = "de_vector2d_option_f64"
+    )]
+    pub scale: Vector2D<Option<f64>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub color: Option<Rgba<u8>>,
+    #[serde(default)]
+    pub opacity: Option<f64>,
+    #[serde(default)]
+    pub depth: Option<f64>,
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_option_f64",
+        deserialize_with = "de_vector2d_option_f64"
+    )]
+    pub parallax: Vector2D<Option<f64>>,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for MoveDecorations {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for MoveDecorations {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// Positions and rotations are offsets from the decoration's own, the other values are
+    /// absolute. The image and depth change as soon as the event starts.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        for data in level.tagged_decorations(&self.tag) {
+            if let (Some(image), Some(now_image)) = (&self.decoration_image, &mut data.image.now) {
+                *now_image = image.clone();
+            }
+            if let (Some(depth), Some(now_depth)) = (self.depth, &mut data.depth.now) {
+                *now_depth = depth;
+            }
+            let (Some(orig_position), Some(now_position)) =
+                (data.position.orig, data.position.now.as_mut())
+            else {
+                continue;
+            };
+            if let Some(offset_x) = self.position_offset.x {
+                now_position.x += (orig_position.x + offset_x - now_position.x) * y;
+            }
+            if let Some(offset_y) = self.position_offset.y {
+                now_position.y += (orig_position.y + offset_y - now_position.y) * y;
+            }
+            let now_pivot_offset = data.pivot_offset.now.as_mut().unwrap();
+            if let Some(pivot_x) = self.pivot_offset.x {
+                now_pivot_offset.x += (pivot_x - now_pivot_offset.x) * y;
+            }
+            if let Some(pivot_y) = self.pivot_offset.y {
+                now_pivot_offset.y += (pivot_y - now_pivot_offset.y) * y;
+            }
+            let now_rotation = data.rotation.now.as_mut().unwrap();
+            if let Some(offset) = self.rotation_offset {
+                *now_rotation += (data.rotation.orig.unwrap() + offset - *now_rotation) * y;
+            }
+            let now_scale = data.scale.now.as_mut().unwrap();
+            if let Some(scale_x) = self.scale.x {
+                now_scale.x += (scale_x - now_scale.x) * y;
+            }
+            if let Some(scale_y) = self.scale.y {
+                now_scale.y += (scale_y - now_scale.y) * y;
+            }
+            let now_parallax = data.parallax.now.as_mut().unwrap();
+            if let Some(parallax_x) = self.parallax.x {
+                now_parallax.x += (parallax_x - now_parallax.x) * y;
+            }
+            if let Some(parallax_y) = self.parallax.y {
+                now_parallax.y += (parallax_y - now_parallax.y) * y;
+            }
+            if let (Some(color), Some(now_color)) = (self.color, data.color.now.as_mut()) {
+                *now_color = lerp_rgba(*now_color, color, y);
+            }
+            if let (Some(opacity), Some(now_opacity)) = (self.opacity, data.opacity.now.as_mut()) {
+                *now_opacity += (opacity - *now_opacity) * y;
+            }
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetText {
+    pub floor: usize,
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    pub dec_text: String,
+    /// The tags of the texts to change.
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub tag: Vec<String>,
+}
+impl Event for SetText {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for SetText {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        for data in level.tagged_decorations(&self.tag) {
+            if let Some(text) = data.text.now.as_mut() {
+                *text = self.dec_text.clone();
+            }
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetObject {
+    pub floor: usize,
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default)]
+    pub duration: f64,
+    /// The tags of the objects to change.
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub tag: Vec<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub planet_color: Option<Rgba<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub planet_tail_color: Option<Rgba<u8>>,
+    #[serde(default)]
+    pub track_angle: Option<f64>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub track_color: Option<Rgba<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub secondary_track_color: Option<Rgba<u8>>,
+    #[serde(default)]
+    pub track_opacity: Option<f64>,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for SetObject {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for SetObject {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        for data in level.tagged_decorations(&self.tag) {
+            for (color, now_color) in [
+                (self.planet_color, &mut data.planet_color.now),
+                (self.planet_tail_color, &mut data.planet_tail_color.now),
+                (self.track_color, &mut data.track_color.now),
+                (
+                    self.secondary_track_color,
+                    &mut data.secondary_track_color.now,
+                ),
+            ] {
+                if let (Some(color), Some(now_color)) = (color, now_color.as_mut()) {
+                    *now_color = lerp_rgba(*now_color, color, y);
+                }
+            }
+            for (value, now_value) in [
+                (self.track_angle, &mut data.track_angle.now),
+                (self.track_opacity, &mut data.track_opacity.now),
+            ] {
+                if let (Some(value), Some(now_value)) = (value, now_value.as_mut()) {
+                    *now_value += (value - *now_value) * y;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDefaultText {
+    pub floor: usize,
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub default_text_color: Option<Rgba<u8>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_rgba_option_u8",
+        deserialize_with = "de_rgba_option_u8"
+    )]
+    pub default_text_shadow_color: Option<Rgba<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub congrats_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perfect_text: Option<String>,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for SetDefaultText {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for SetDefaultText {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// The colors are eased over the duration, the texts change as soon as the event starts.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let default_text = &mut level.default_text;
+        for (color, now_color) in [
+            (self.default_text_color, &mut default_text.color.now),
+            (
+                self.default_text_shadow_color,
+                &mut default_text.shadow_color.now,
+            ),
+        ] {
+            if let (Some(color), Some(now_color)) = (color, now_color.as_mut()) {
+                *now_color = lerp_rgba(*now_color, color, y);
+            }
+        }
+        if let Some(congrats_text) = &self.congrats_text {
+            default_text.congrats_text.now = Some(congrats_text.clone());
+        }
+        if let Some(perfect_text) = &self.perfect_text {
+            default_text.perfect_text.now = Some(perfect_text.clone());
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
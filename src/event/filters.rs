@@ -0,0 +1,600 @@
+use super::*;
+use crate::*;
+use rgb::Rgba;
+use serde::{Deserialize, Serialize};
+use std::error;
+use vector2d::Vector2D;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetFilter {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    pub filter: Filter,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub enabled: bool,
+    #[serde(default = "f64_100")]
+    pub intensity: f64,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub ease: Easing,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub disable_others: bool,
+}
+impl Event for SetFilter {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for SetFilter {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// A filter that is turned on starts from no intensity; turning it off is immediate.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let effects = &mut level.screen_effects;
+        if self.disable_others {
+            effects.disable_other_filters(&self.filter);
+        }
+        if self.enabled {
+            let active = effects.enable_filter(&self.filter);
+            active.intensity += (self.intensity - active.intensity) * y;
+        } else {
+            effects.disable_filter(&self.filter);
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetFilterAdvanced {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    pub filter: Filter,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub ease: Easing,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub disable_others: bool,
+    /// The filter's own properties, as the game writes them.
+    #[serde(default)]
+    pub filter_properties: String,
+}
+impl Event for SetFilterAdvanced {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for SetFilterAdvanced {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// The properties are not interpreted, so the filter is at full intensity once the
+    /// duration is over.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let effects = &mut level.screen_effects;
+        if self.disable_others {
+            effects.disable_other_filters(&self.filter);
+        }
+        if self.enabled {
+            let active = effects.enable_filter(&self.filter);
+            active.intensity += (100.0 - active.intensity) * y;
+            active.properties = Some(self.filter_properties.clone());
+        } else {
+            effects.disable_filter(&self.filter);
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bloom {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub enabled: bool,
+    #[serde(default = "f64_50")]
+    pub threshold: f64,
+    #[serde(default = "f64_100")]
+    pub intensity: f64,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub color: Rgba<u8>,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for Bloom {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for Bloom {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// Bloom that is turned on starts from no intensity; turning it off is immediate.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let bloom = &mut level.screen_effects.bloom;
+        if !self.enabled {
+            *bloom = None;
+            return Ok(());
+        }
+        let bloom = bloom.get_or_insert(BloomState {
+            threshold: self.threshold,
+            intensity: 0.0,
+            color: self.color,
+        });
+        bloom.threshold += (self.threshold - bloom.threshold) * y;
+        bloom.intensity += (self.intensity - bloom.intensity) * y;
+        bloom.color = lerp_rgba(bloom.color, self.color, y);
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Flash {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub plane: FlashPlane,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub start_color: Rgba<u8>,
+    #[serde(default = "f64_100")]
+    pub start_opacity: f64,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub end_color: Rgba<u8>,
+    #[serde(default)]
+    pub end_opacity: f64,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for Flash {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for Flash {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let with_opacity = |color: Rgba<u8>, opacity: f64| Rgba {
+            a: (opacity.clamp(0.0, 100.0) / 100.0 * 255.0).round() as u8,
+            ..color
+        };
+        let color = lerp_rgba(
+            with_opacity(self.start_color, self.start_opacity),
+            with_opacity(self.end_color, self.end_opacity),
+            y,
+        );
+        let flash = match self.plane {
+            FlashPlane::Foreground => &mut level.screen_effects.foreground_flash,
+            FlashPlane::Background => &mut level.screen_effects.background_flash,
+        };
+        *flash = if y >= 1.0 && color.a == 0 {
+            None
+        } else {
+            Some(color)
+        };
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HallOfMirrors {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub enabled: bool,
+}
+impl Event for HallOfMirrors {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for HallOfMirrors {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds >= e_seconds {
+            level.screen_effects.hall_of_mirrors = self.enabled;
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShakeScreen {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default = "f64_1")]
+    pub duration: f64,
+    #[serde(default = "f64_100")]
+    pub strength: f64,
+    #[serde(default = "f64_100")]
+    pub intensity: f64,
+    #[serde(default)]
+    pub ease: Easing,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub fade_out: bool,
+}
+impl Event for ShakeScreen {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for ShakeScreen {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// The game shakes the camera randomly, which is not modelled, so this changes nothing.
+    fn apply(
+        &self,
+        _data: (f64, f64),
+        _level: &mut Level,
+        _seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenTile {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(
+        default = "vector2d_1",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub tile: Vector2D<f64>,
+    #[serde(default)]
+    pub ease: Easing,
+}
+impl Event for ScreenTile {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for ScreenTile {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let y = progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            self.duration,
+            self.ease,
+        )?;
+        let tiling = &mut level.screen_effects.tiling;
+        *tiling += (self.tile - *tiling) * y;
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenScroll {
+    pub floor: usize,
+    #[serde(
+        default,
+        serialize_with = "ser_event_tag",
+        deserialize_with = "de_event_tag"
+    )]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    /// The scroll velocity, in screens per second.
+    #[serde(
+        default,
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub scroll: Vector2D<f64>,
+}
+impl Event for ScreenScroll {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for ScreenScroll {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// The velocity changes at once; the offset keeps what was scrolled before.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds >= e_seconds {
+            level.screen_effects.scroll(self.scroll, e_seconds, seconds);
+        }
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
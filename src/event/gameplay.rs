@@ -177,3 +177,26 @@ impl TryFrom<&str> for AngleCorrectionDir {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiPlanet {
+    pub floor: usize,
+    pub planets: Planets,
+}
+impl Event for MultiPlanet {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl StaticEvent for MultiPlanet {
+    fn apply(&self, data: &mut TileData) {
+        data.planets = Some(self.planets);
+    }
+}
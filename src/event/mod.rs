@@ -13,9 +13,63 @@ mod modifiers;
 pub use modifiers::*;
 mod dlc;
 pub use dlc::*;
+mod decorations;
+pub use decorations::*;
+mod filters;
+pub use filters::*;
 
 use crate::*;
 
+/// The eased progress of an event that started at `e_seconds` and lasts `duration` beats.
+fn progress(
+    level: &Level,
+    floor: usize,
+    e_seconds: f64,
+    seconds: f64,
+    duration: f64,
+    ease: Easing,
+) -> Result<f64, Box<dyn error::Error>> {
+    if duration == 0.0 {
+        return Ok(1.0);
+    }
+    let spb = bpm2crotchet(level.get_bpm_by_floor_seconds(floor, e_seconds)?);
+    Ok(ease.calc((seconds - e_seconds) / spb / duration))
+}
+
+/// The tiles from `start_tile` to `end_tile` that an event starting at `e_seconds` has
+/// reached by `seconds`, with the eased progress of each over `duration` beats. Each tile
+/// starts `gap_length` beats after the one before it.
+#[allow(clippy::too_many_arguments)]
+fn staggered_progress(
+    level: &Level,
+    floor: usize,
+    e_seconds: f64,
+    seconds: f64,
+    (start_tile, end_tile): (RelativeIndex, RelativeIndex),
+    gap_length: f64,
+    duration: f64,
+    ease: Easing,
+) -> Result<Vec<(usize, f64)>, Box<dyn error::Error>> {
+    let spb = bpm2crotchet(level.get_bpm_by_floor_seconds(floor, e_seconds)?);
+    let last_floor = level.tiles.len() - 1;
+    let start = start_tile.calc(floor, last_floor);
+    let end = end_tile.calc(floor, last_floor).min(last_floor);
+    let mut tiles = vec![];
+    for f in start..=end {
+        let f_seconds = e_seconds + (f - start) as f64 * gap_length * spb;
+        if seconds < f_seconds {
+            break;
+        }
+        let y = if duration == 0.0 {
+            1.0
+        } else {
+            ease.calc((seconds - f_seconds) / spb / duration)
+        };
+        tiles.push((f, y));
+    }
+    Ok(tiles)
+}
+
 pub trait Event: std::fmt::Debug + Clone + Serialize + for<'a> Deserialize<'a> {
     fn floor(&self) -> usize;
     fn floor_mut(&mut self) -> &mut usize;
@@ -74,6 +128,22 @@ pub enum EventData {
         beats: Option<f64>,
         seconds: Option<f64>,
     },
+    /// An action whose `eventType` is not modelled (or could not be read into its model).
+    /// The original JSON object is kept and written back verbatim.
+    Unknown {
+        event_type: String,
+        floor: usize,
+        raw_json: serde_json_lenient::Map<String, serde_json_lenient::Value>,
+    },
+}
+impl EventData {
+    pub fn floor(&self) -> usize {
+        match self {
+            EventData::Static { event } => event.floor(),
+            EventData::Dynamic { event, .. } => event.floor(),
+            EventData::Unknown { floor, .. } => *floor,
+        }
+    }
 }
 impl Serialize for EventData {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -83,6 +153,7 @@ impl Serialize for EventData {
         match self {
             EventData::Static { event } => event.serialize(serializer),
             EventData::Dynamic { event, .. } => event.serialize(serializer),
+            EventData::Unknown { raw_json, .. } => raw_json.serialize(serializer),
         }
     }
 }
@@ -112,14 +183,14 @@ impl RelativeIndex {
                 if self.index >= 0 {
                     this_floor.saturating_add(self.index as usize)
                 } else {
-                    this_floor.saturating_sub((-self.index) as usize)
+                    this_floor.saturating_sub(self.index.unsigned_abs())
                 }
             }
             RelativeToTile::End => {
                 if self.index >= 0 {
                     last_floor
                 } else {
-                    last_floor.saturating_sub(self.index as usize)
+                    last_floor.saturating_sub(self.index.unsigned_abs())
                 }
             }
         }
@@ -136,6 +207,8 @@ pub enum StaticEvents {
     PositionTrack(PositionTrack),
     SetHitsound(SetHitsound),
     Hold(Hold),
+    AnimateTrack(AnimateTrack),
+    MultiPlanet(MultiPlanet),
 }
 impl Event for StaticEvents {
     fn floor(&self) -> usize {
@@ -147,6 +220,8 @@ impl Event for StaticEvents {
             Self::PositionTrack(event) => event.floor(),
             Self::SetHitsound(event) => event.floor(),
             Self::Hold(event) => event.floor(),
+            Self::AnimateTrack(event) => event.floor(),
+            Self::MultiPlanet(event) => event.floor(),
         }
     }
     fn floor_mut(&mut self) -> &mut usize {
@@ -158,6 +233,8 @@ impl Event for StaticEvents {
             Self::PositionTrack(event) => event.floor_mut(),
             Self::SetHitsound(event) => event.floor_mut(),
             Self::Hold(event) => event.floor_mut(),
+            Self::AnimateTrack(event) => event.floor_mut(),
+            Self::MultiPlanet(event) => event.floor_mut(),
         }
     }
     fn set_floor(&mut self, new_floor: usize) {
@@ -169,6 +246,8 @@ impl Event for StaticEvents {
             Self::PositionTrack(event) => event.set_floor(new_floor),
             Self::SetHitsound(event) => event.set_floor(new_floor),
             Self::Hold(event) => event.set_floor(new_floor),
+            Self::AnimateTrack(event) => event.set_floor(new_floor),
+            Self::MultiPlanet(event) => event.set_floor(new_floor),
         }
     }
 }
@@ -182,6 +261,8 @@ impl StaticEvent for StaticEvents {
             Self::PositionTrack(position_track) => position_track.apply(data),
             Self::SetHitsound(set_hitsound) => set_hitsound.apply(data),
             Self::Hold(hold) => hold.apply(data),
+            Self::AnimateTrack(animate_track) => animate_track.apply(data),
+            Self::MultiPlanet(multi_planet) => multi_planet.apply(data),
         }
     }
 }
@@ -194,6 +275,19 @@ pub enum DynamicEvents {
     MoveTrack(MoveTrack),
     MoveCamera(MoveCamera),
     RepeatEvents(RepeatEvents),
+    MoveDecorations(Box<MoveDecorations>),
+    SetText(SetText),
+    SetObject(SetObject),
+    SetDefaultText(SetDefaultText),
+    SetFilter(SetFilter),
+    SetFilterAdvanced(SetFilterAdvanced),
+    Bloom(Bloom),
+    Flash(Flash),
+    HallOfMirrors(HallOfMirrors),
+    ShakeScreen(ShakeScreen),
+    ScreenTile(ScreenTile),
+    ScreenScroll(ScreenScroll),
+    CustomBackground(CustomBackground),
 }
 impl DynamicEvents {
     pub fn self_has_event_tag(&self) -> bool {
@@ -203,6 +297,19 @@ impl DynamicEvents {
             Self::MoveTrack(_) => MoveTrack::has_event_tag(),
             Self::MoveCamera(_) => MoveCamera::has_event_tag(),
             Self::RepeatEvents(_) => RepeatEvents::has_event_tag(),
+            Self::MoveDecorations(_) => MoveDecorations::has_event_tag(),
+            Self::SetText(_) => SetText::has_event_tag(),
+            Self::SetObject(_) => SetObject::has_event_tag(),
+            Self::SetDefaultText(_) => SetDefaultText::has_event_tag(),
+            Self::SetFilter(_) => SetFilter::has_event_tag(),
+            Self::SetFilterAdvanced(_) => SetFilterAdvanced::has_event_tag(),
+            Self::Bloom(_) => Bloom::has_event_tag(),
+            Self::Flash(_) => Flash::has_event_tag(),
+            Self::HallOfMirrors(_) => HallOfMirrors::has_event_tag(),
+            Self::ShakeScreen(_) => ShakeScreen::has_event_tag(),
+            Self::ScreenTile(_) => ScreenTile::has_event_tag(),
+            Self::ScreenScroll(_) => ScreenScroll::has_event_tag(),
+            Self::CustomBackground(_) => CustomBackground::has_event_tag(),
         }
     }
 }
@@ -214,6 +321,19 @@ impl Event for DynamicEvents {
             Self::MoveTrack(event) => event.floor(),
             Self::MoveCamera(event) => event.floor(),
             Self::RepeatEvents(event) => event.floor(),
+            Self::MoveDecorations(event) => event.floor(),
+            Self::SetText(event) => event.floor(),
+            Self::SetObject(event) => event.floor(),
+            Self::SetDefaultText(event) => event.floor(),
+            Self::SetFilter(event) => event.floor(),
+            Self::SetFilterAdvanced(event) => event.floor(),
+            Self::Bloom(event) => event.floor(),
+            Self::Flash(event) => event.floor(),
+            Self::HallOfMirrors(event) => event.floor(),
+            Self::ShakeScreen(event) => event.floor(),
+            Self::ScreenTile(event) => event.floor(),
+            Self::ScreenScroll(event) => event.floor(),
+            Self::CustomBackground(event) => event.floor(),
         }
     }
     fn floor_mut(&mut self) -> &mut usize {
@@ -223,6 +343,19 @@ impl Event for DynamicEvents {
             Self::MoveTrack(event) => event.floor_mut(),
             Self::MoveCamera(event) => event.floor_mut(),
             Self::RepeatEvents(event) => event.floor_mut(),
+            Self::MoveDecorations(event) => event.floor_mut(),
+            Self::SetText(event) => event.floor_mut(),
+            Self::SetObject(event) => event.floor_mut(),
+            Self::SetDefaultText(event) => event.floor_mut(),
+            Self::SetFilter(event) => event.floor_mut(),
+            Self::SetFilterAdvanced(event) => event.floor_mut(),
+            Self::Bloom(event) => event.floor_mut(),
+            Self::Flash(event) => event.floor_mut(),
+            Self::HallOfMirrors(event) => event.floor_mut(),
+            Self::ShakeScreen(event) => event.floor_mut(),
+            Self::ScreenTile(event) => event.floor_mut(),
+            Self::ScreenScroll(event) => event.floor_mut(),
+            Self::CustomBackground(event) => event.floor_mut(),
         }
     }
     fn set_floor(&mut self, new_floor: usize) {
@@ -232,6 +365,19 @@ impl Event for DynamicEvents {
             Self::MoveTrack(event) => event.set_floor(new_floor),
             Self::MoveCamera(event) => event.set_floor(new_floor),
             Self::RepeatEvents(event) => event.set_floor(new_floor),
+            Self::MoveDecorations(event) => event.set_floor(new_floor),
+            Self::SetText(event) => event.set_floor(new_floor),
+            Self::SetObject(event) => event.set_floor(new_floor),
+            Self::SetDefaultText(event) => event.set_floor(new_floor),
+            Self::SetFilter(event) => event.set_floor(new_floor),
+            Self::SetFilterAdvanced(event) => event.set_floor(new_floor),
+            Self::Bloom(event) => event.set_floor(new_floor),
+            Self::Flash(event) => event.set_floor(new_floor),
+            Self::HallOfMirrors(event) => event.set_floor(new_floor),
+            Self::ShakeScreen(event) => event.set_floor(new_floor),
+            Self::ScreenTile(event) => event.set_floor(new_floor),
+            Self::ScreenScroll(event) => event.set_floor(new_floor),
+            Self::CustomBackground(event) => event.set_floor(new_floor),
         }
     }
 }
@@ -248,6 +394,19 @@ impl DynamicEvent for DynamicEvents {
             Self::MoveTrack(e) => e.apply(data, level, seconds),
             Self::MoveCamera(e) => e.apply(data, level, seconds),
             Self::RepeatEvents(e) => e.apply(data, level, seconds),
+            Self::MoveDecorations(e) => e.apply(data, level, seconds),
+            Self::SetText(e) => e.apply(data, level, seconds),
+            Self::SetObject(e) => e.apply(data, level, seconds),
+            Self::SetDefaultText(e) => e.apply(data, level, seconds),
+            Self::SetFilter(e) => e.apply(data, level, seconds),
+            Self::SetFilterAdvanced(e) => e.apply(data, level, seconds),
+            Self::Bloom(e) => e.apply(data, level, seconds),
+            Self::Flash(e) => e.apply(data, level, seconds),
+            Self::HallOfMirrors(e) => e.apply(data, level, seconds),
+            Self::ShakeScreen(e) => e.apply(data, level, seconds),
+            Self::ScreenTile(e) => e.apply(data, level, seconds),
+            Self::ScreenScroll(e) => e.apply(data, level, seconds),
+            Self::CustomBackground(e) => e.apply(data, level, seconds),
         }
     }
     fn angle_offset(&self) -> f64 {
@@ -257,6 +416,19 @@ impl DynamicEvent for DynamicEvents {
             Self::MoveTrack(e) => e.angle_offset(),
             Self::MoveCamera(e) => e.angle_offset(),
             Self::RepeatEvents(e) => e.angle_offset(),
+            Self::MoveDecorations(e) => e.angle_offset(),
+            Self::SetText(e) => e.angle_offset(),
+            Self::SetObject(e) => e.angle_offset(),
+            Self::SetDefaultText(e) => e.angle_offset(),
+            Self::SetFilter(e) => e.angle_offset(),
+            Self::SetFilterAdvanced(e) => e.angle_offset(),
+            Self::Bloom(e) => e.angle_offset(),
+            Self::Flash(e) => e.angle_offset(),
+            Self::HallOfMirrors(e) => e.angle_offset(),
+            Self::ShakeScreen(e) => e.angle_offset(),
+            Self::ScreenTile(e) => e.angle_offset(),
+            Self::ScreenScroll(e) => e.angle_offset(),
+            Self::CustomBackground(e) => e.angle_offset(),
         }
     }
     fn has_event_tag() -> bool {
@@ -269,6 +441,19 @@ impl DynamicEvent for DynamicEvents {
             Self::MoveTrack(e) => e.event_tag(),
             Self::MoveCamera(e) => e.event_tag(),
             Self::RepeatEvents(e) => e.event_tag(),
+            Self::MoveDecorations(e) => e.event_tag(),
+            Self::SetText(e) => e.event_tag(),
+            Self::SetObject(e) => e.event_tag(),
+            Self::SetDefaultText(e) => e.event_tag(),
+            Self::SetFilter(e) => e.event_tag(),
+            Self::SetFilterAdvanced(e) => e.event_tag(),
+            Self::Bloom(e) => e.event_tag(),
+            Self::Flash(e) => e.event_tag(),
+            Self::HallOfMirrors(e) => e.event_tag(),
+            Self::ShakeScreen(e) => e.event_tag(),
+            Self::ScreenTile(e) => e.event_tag(),
+            Self::ScreenScroll(e) => e.event_tag(),
+            Self::CustomBackground(e) => e.event_tag(),
         }
     }
     fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
@@ -278,6 +463,19 @@ impl DynamicEvent for DynamicEvents {
             Self::MoveTrack(e) => e.event_tag_mut(),
             Self::MoveCamera(e) => e.event_tag_mut(),
             Self::RepeatEvents(e) => e.event_tag_mut(),
+            Self::MoveDecorations(e) => e.event_tag_mut(),
+            Self::SetText(e) => e.event_tag_mut(),
+            Self::SetObject(e) => e.event_tag_mut(),
+            Self::SetDefaultText(e) => e.event_tag_mut(),
+            Self::SetFilter(e) => e.event_tag_mut(),
+            Self::SetFilterAdvanced(e) => e.event_tag_mut(),
+            Self::Bloom(e) => e.event_tag_mut(),
+            Self::Flash(e) => e.event_tag_mut(),
+            Self::HallOfMirrors(e) => e.event_tag_mut(),
+            Self::ShakeScreen(e) => e.event_tag_mut(),
+            Self::ScreenTile(e) => e.event_tag_mut(),
+            Self::ScreenScroll(e) => e.event_tag_mut(),
+            Self::CustomBackground(e) => e.event_tag_mut(),
         }
     }
 }
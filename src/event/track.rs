@@ -18,6 +18,11 @@ pub struct ColorTrack {
     pub track_color_pulse: TrackColorPulse,
     pub track_pulse_length: u32,
     pub track_style: TrackStyle,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_glow_intensity: Option<f64>,
+    /// Only this tile takes the colors; the next tile goes back to the colors before it.
+    #[serde(default)]
+    pub just_this_tile: bool,
 }
 impl Event for ColorTrack {
     fn floor(&self) -> usize {
@@ -39,6 +44,9 @@ impl StaticEvent for ColorTrack {
         data.color_pulse.orig = Some(self.track_color_pulse);
         data.pulse_length.orig = Some(self.track_pulse_length);
         data.style.orig = Some(self.track_style);
+        if let Some(glow_intensity) = self.track_glow_intensity {
+            data.glow_intensity.orig = Some(glow_intensity);
+        }
     }
 }
 
@@ -62,6 +70,12 @@ pub struct RecolorTrack {
     pub track_color_pulse: TrackColorPulse,
     pub track_pulse_length: u32,
     pub track_style: TrackStyle,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_glow_intensity: Option<f64>,
+    #[serde(default)]
+    pub duration: f64,
+    #[serde(default)]
+    pub ease: Easing,
 }
 impl Event for RecolorTrack {
     fn floor(&self) -> usize {
@@ -74,6 +88,43 @@ impl Event for RecolorTrack {
         self.floor = new_floor
     }
 }
+impl RecolorTrack {
+    /// The tiles this RecolorTrack has reached by `seconds`, with how far each has changed.
+    pub(crate) fn tiles(
+        &self,
+        level: &Level,
+        e_seconds: f64,
+        seconds: f64,
+    ) -> Result<Vec<(usize, f64)>, Box<dyn error::Error>> {
+        staggered_progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            (self.start_tile, self.end_tile),
+            self.gap_length as f64,
+            self.duration,
+            self.ease,
+        )
+    }
+    /// Changes the colors of a tile `y` of the way towards this RecolorTrack.
+    pub(crate) fn recolor(&self, data: &mut TileData, y: f64) {
+        data.color_type.now = Some(self.track_color_type);
+        data.color_pulse.now = Some(self.track_color_pulse);
+        data.pulse_length.now = Some(self.track_pulse_length);
+        data.style.now = Some(self.track_style);
+        let now_color = data.color.now.as_mut().unwrap();
+        *now_color = lerp_rgba(*now_color, self.track_color, y);
+        let now_secondary_color = data.secondary_color.now.as_mut().unwrap();
+        *now_secondary_color = lerp_rgba(*now_secondary_color, self.secondary_track_color, y);
+        let now_anim_duration = data.color_anim_duration.now.as_mut().unwrap();
+        *now_anim_duration += (self.track_color_anim_duration - *now_anim_duration) * y;
+        if let Some(glow_intensity) = self.track_glow_intensity {
+            let now_glow_intensity = data.glow_intensity.now.as_mut().unwrap();
+            *now_glow_intensity += (glow_intensity - *now_glow_intensity) * y;
+        }
+    }
+}
 impl DynamicEvent for RecolorTrack {
     fn angle_offset(&self) -> f64 {
         self.angle_offset
@@ -88,23 +139,8 @@ impl DynamicEvent for RecolorTrack {
         if seconds < e_seconds {
             return Ok(());
         }
-        // let bpm = level.get_bpm_by_seconds(e_seconds)?;
-        let bpm = level.get_bpm_by_floor_seconds(self.floor, e_seconds)?;
-        let spb = bpm2crotchet(bpm);
-        let start = self.start_tile.calc(self.floor, level.tiles.len() - 1);
-        let end = self.end_tile.calc(self.floor, level.tiles.len() - 1);
-        for f in start..=end.min(level.tiles.len() - 1) {
-            if seconds < e_seconds + (f as f64 * self.gap_length as f64) * spb {
-                return Ok(());
-            }
-            let data = &mut level.tiles[f].data;
-            data.color_type.now = Some(self.track_color_type);
-            data.color.now = Some(self.track_color);
-            data.secondary_color.now = Some(self.secondary_track_color);
-            data.color_anim_duration.now = Some(self.track_color_anim_duration);
-            data.color_pulse.now = Some(self.track_color_pulse);
-            data.pulse_length.now = Some(self.track_pulse_length);
-            data.style.now = Some(self.track_style);
+        for (f, y) in self.tiles(level, e_seconds, seconds)? {
+            self.recolor(&mut level.tiles[f].data, y);
         }
         Ok(())
     }
@@ -148,6 +184,9 @@ pub struct MoveTrack {
     #[serde(default)]
     pub opacity: Option<f64>,
     pub ease: Easing,
+    /// Only moves the track when the visual effects are at their maximum.
+    #[serde(default)]
+    pub max_vfx_only: bool,
 }
 impl Event for MoveTrack {
     fn floor(&self) -> usize {
@@ -171,30 +210,29 @@ impl DynamicEvent for MoveTrack {
         seconds: f64,
     ) -> Result<(), Box<dyn error::Error>> {
         let (_e_beats, e_seconds) = data;
-        // let bpm = level.get_bpm_by_seconds(e_seconds)?;
-        let bpm = level.get_bpm_by_floor_seconds(self.floor, e_seconds)?;
-        let spb = bpm2crotchet(bpm);
-        let (x, y);
-        if seconds < e_seconds {
+        if seconds < e_seconds || (self.max_vfx_only && !level.max_vfx()) {
             return Ok(());
         }
-        if self.duration == 0.0 {
-            /* x = 1.0; */
-            y = 1.0;
-        } else {
-            x = (seconds - e_seconds) / spb / self.duration;
-            y = self.ease.calc(x);
-        }
-
-        let start = self.start_tile.calc(self.floor, level.tiles.len() - 1);
-        let end = self.end_tile.calc(self.floor, level.tiles.len() - 1);
-        for f in start..=end.min(level.tiles.len() - 1) {
+        let tiles = staggered_progress(
+            level,
+            self.floor,
+            e_seconds,
+            seconds,
+            (self.start_tile, self.end_tile),
+            self.gap_length,
+            self.duration,
+            self.ease,
+        )?;
+        for (f, y) in tiles {
             let data = &mut level.tiles[f].data;
             let now_position = data.position.now.as_mut().unwrap();
             let orig_position = data.position.orig.unwrap();
             let now_rotation = data.rotation.now.as_mut().unwrap();
+            let orig_rotation = data.rotation.orig.unwrap();
             let now_scale = data.scale.now.as_mut().unwrap();
             let now_opacity = data.opacity.now.as_mut().unwrap();
+            // Position and rotation are offsets from where PositionTrack left the tile,
+            // scale and opacity are absolute.
             if let Some(offset_x) = self.position_offset.x {
                 now_position.x += (orig_position.x + offset_x - now_position.x) * y;
             }
@@ -202,7 +240,7 @@ impl DynamicEvent for MoveTrack {
                 now_position.y += (orig_position.y + offset_y - now_position.y) * y;
             }
             if let Some(offset) = self.rotation_offset {
-                *now_rotation += (offset - *now_rotation) * y;
+                *now_rotation += (orig_rotation + offset - *now_rotation) * y;
             }
             if let Some(scale_x) = self.scale.x {
                 now_scale.x += (scale_x - now_scale.x) * y;
@@ -246,8 +284,12 @@ pub struct PositionTrack {
     pub position_offset: Vector2D<f64>,
     #[serde(default = "relative_to_default")]
     pub relative_to: RelativeIndex,
-    #[serde(default)]
-    pub scale: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
     #[serde(default, deserialize_with = "de_bool")]
     pub just_this_tile: bool,
     #[serde(deserialize_with = "de_bool")]
@@ -267,5 +309,59 @@ impl Event for PositionTrack {
     }
 }
 impl StaticEvent for PositionTrack {
-    fn apply(&self, _data: &mut TileData) {}
+    /// Sets the rotation, scale and opacity of this tile; the position is placed in
+    /// [`Level::parse`] since it depends on the other tiles.
+    fn apply(&self, data: &mut TileData) {
+        if let Some(rotation) = self.rotation {
+            data.rotation.orig = Some(rotation);
+        }
+        if let Some(scale) = self.scale {
+            data.scale.orig = Some(Vector2D::new(scale, scale));
+        }
+        if let Some(opacity) = self.opacity {
+            data.opacity.orig = Some(opacity);
+        }
+    }
+}
+
+/// Changes the appear and disappear animations from this tile on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimateTrack {
+    pub floor: usize,
+    #[serde(default)]
+    pub track_animation: Option<TrackAnimation>,
+    #[serde(default)]
+    pub beats_ahead: Option<f64>,
+    #[serde(default)]
+    pub track_disappear_animation: Option<TrackDisappearAnimation>,
+    #[serde(default)]
+    pub beats_behind: Option<f64>,
+}
+impl Event for AnimateTrack {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl StaticEvent for AnimateTrack {
+    fn apply(&self, data: &mut TileData) {
+        if let Some(track_animation) = self.track_animation {
+            data.track_animation = Some(track_animation);
+        }
+        if let Some(beats_ahead) = self.beats_ahead {
+            data.beats_ahead = Some(beats_ahead);
+        }
+        if let Some(track_disappear_animation) = self.track_disappear_animation {
+            data.track_disappear_animation = Some(track_disappear_animation);
+        }
+        if let Some(beats_behind) = self.beats_behind {
+            data.beats_behind = Some(beats_behind);
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use super::*;
 use crate::*;
+use rgb::Rgba;
 use serde::{Deserialize, Serialize};
 use std::error;
 use vector2d::Vector2D;
@@ -59,3 +60,92 @@ impl DynamicEvent for MoveCamera {
         Some(&mut self.event_tag)
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomBackground {
+    pub floor: usize,
+    #[serde(serialize_with = "ser_event_tag", deserialize_with = "de_event_tag")]
+    pub event_tag: Vec<String>,
+    #[serde(default)]
+    pub angle_offset: f64,
+    #[serde(
+        default = "rgba_000000",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub color: Rgba<u8>,
+    #[serde(default, alias = "image")]
+    pub bg_image: String,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub image_color: Rgba<u8>,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax: Vector2D<f64>,
+    #[serde(default)]
+    pub bg_display_mode: BgDisplayMode,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub image_smoothing: bool,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_rot: bool,
+    #[serde(rename = "loopBG", default, deserialize_with = "de_bool")]
+    pub loop_bg: bool,
+    /// Older levels call this `unscaledSize`.
+    #[serde(default = "f64_100", alias = "unscaledSize")]
+    pub scaling_ratio: f64,
+}
+impl Event for CustomBackground {
+    fn floor(&self) -> usize {
+        self.floor
+    }
+    fn floor_mut(&mut self) -> &mut usize {
+        &mut self.floor
+    }
+    fn set_floor(&mut self, new_floor: usize) {
+        self.floor = new_floor
+    }
+}
+impl DynamicEvent for CustomBackground {
+    fn angle_offset(&self) -> f64 {
+        self.angle_offset
+    }
+    /// The background changes at once.
+    fn apply(
+        &self,
+        data: (f64, f64),
+        level: &mut Level,
+        seconds: f64,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (_e_beats, e_seconds) = data;
+        if seconds < e_seconds {
+            return Ok(());
+        }
+        let background = &mut level.background;
+        background.color.now = Some(self.color);
+        background.image.now = Some(self.bg_image.clone());
+        background.image_color.now = Some(self.image_color);
+        background.parallax.now = Some(self.parallax);
+        background.display_mode.now = Some(self.bg_display_mode);
+        background.image_smoothing.now = Some(self.image_smoothing);
+        background.lock_rot.now = Some(self.lock_rot);
+        background.loop_bg.now = Some(self.loop_bg);
+        background.scaling_ratio.now = Some(self.scaling_ratio);
+        Ok(())
+    }
+    fn has_event_tag() -> bool {
+        true
+    }
+    fn event_tag(&self) -> Option<&Vec<String>> {
+        Some(&self.event_tag)
+    }
+    fn event_tag_mut(&mut self) -> Option<&mut Vec<String>> {
+        Some(&mut self.event_tag)
+    }
+}
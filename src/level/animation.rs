@@ -0,0 +1,27 @@
+//! When tiles appear and disappear.
+//!
+//! Only the moments follow from the level: a tile appears once the planet is `beatsAhead`
+//! beats before it and disappears once the planet is `beatsBehind` beats past it. The motion
+//! of the animations themselves (their length, distances and easing) is the game's own and is
+//! not modelled, so a renderer animates the tile from [`TileData::track_animation`] and
+//! [`TileData::track_disappear_animation`] at those moments.
+
+use crate::*;
+
+impl Level {
+    /// Sets whether every tile is shown at `seconds`. Tiles without an appear or disappear
+    /// animation are always shown on that side.
+    pub(crate) fn animate_track(&mut self, seconds: f64) {
+        let beats = self.tempo_map.seconds2beats(seconds);
+        for tile in &mut self.tiles {
+            let data = &mut tile.data;
+            let tile_beats = data.beats.unwrap();
+            let appeared = data.track_animation == Some(TrackAnimation::None)
+                || beats >= tile_beats - data.beats_ahead.unwrap();
+            let disappeared = data.track_disappear_animation
+                != Some(TrackDisappearAnimation::None)
+                && beats > tile_beats + data.beats_behind.unwrap();
+            data.visible.now = Some(appeared && !disappeared);
+        }
+    }
+}
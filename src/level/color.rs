@@ -0,0 +1,132 @@
+use crate::*;
+use event::*;
+use rgb::Rgba;
+use std::error;
+use std::f64::consts::TAU;
+
+/// The color of a tile of `color_type`. `phase` is how far the tile is through its color
+/// animation, from 0.0 to 1.0.
+fn track_color(
+    color_type: TrackColorType,
+    color: Rgba<u8>,
+    secondary_color: Rgba<u8>,
+    phase: f64,
+    floor: usize,
+    glow_intensity: f64,
+) -> Rgba<u8> {
+    match color_type {
+        // Volume follows the loudness of the song, which the level does not know about, so it
+        // is drawn in the track color.
+        TrackColorType::Single | TrackColorType::Volume => color,
+        TrackColorType::Stripes => {
+            if floor.is_multiple_of(2) {
+                color
+            } else {
+                secondary_color
+            }
+        }
+        TrackColorType::Glow => {
+            let t = (1.0 - (phase * TAU).cos()) / 2.0 * glow_intensity / 100.0;
+            lerp_rgba(color, secondary_color, t.clamp(0.0, 1.0))
+        }
+        TrackColorType::Blink => lerp_rgba(color, secondary_color, 1.0 - phase),
+        TrackColorType::Switch => {
+            if phase < 0.5 {
+                color
+            } else {
+                secondary_color
+            }
+        }
+        TrackColorType::Rainbow => Rgba {
+            a: color.a,
+            ..hue2rgba(phase)
+        },
+    }
+}
+
+/// The fully saturated color of `hue`, from 0.0 to 1.0.
+fn hue2rgba(hue: f64) -> Rgba<u8> {
+    let channel = |n: f64| {
+        let k = (n + hue * 6.0) % 6.0;
+        let value = 1.0 - k.min(4.0 - k).clamp(0.0, 1.0);
+        (value * 255.0).round() as u8
+    };
+    Rgba {
+        r: channel(5.0),
+        g: channel(3.0),
+        b: channel(1.0),
+        a: 255,
+    }
+}
+
+impl Level {
+    /// The color the tile at `floor` shows at `seconds`, with the RecolorTracks that have
+    /// reached it by then. It does not depend on the last [`Level::update`].
+    ///
+    /// [`TrackColorType::Volume`] follows the loudness of the song, which is not known here, so
+    /// such tiles are given their track color. Pulses delay (`Forward`) or advance (`Backward`) the color animation of each tile by
+    /// its place in a group of `pulse_length` tiles.
+    pub fn tile_color(
+        &self,
+        floor: usize,
+        seconds: f64,
+    ) -> Result<Rgba<u8>, Box<dyn error::Error>> {
+        if !self.parsed {
+            return Err(Box::new(LevelIsNotParsedError {
+                calling_function: "tile_color",
+            }));
+        }
+        let Some(tile) = self.tiles.get(floor) else {
+            return Err(Box::new(FloorOutOfRangeError {
+                floor,
+                tile_count: self.tiles.len(),
+            }));
+        };
+        let mut data = tile.data;
+        data.orig2now();
+        for event in &self.dynamic_events {
+            let EventData::Dynamic {
+                event: DynamicEvents::RecolorTrack(recolor_track),
+                seconds: Some(e_seconds),
+                ..
+            } = event
+            else {
+                continue;
+            };
+            if seconds < *e_seconds {
+                break;
+            }
+            for (f, y) in recolor_track.tiles(self, *e_seconds, seconds)? {
+                if f == floor {
+                    recolor_track.recolor(&mut data, y);
+                }
+            }
+        }
+        let color_type = data.color_type.current()?;
+        let duration = data.color_anim_duration.current()?;
+        let pulse_length = data.pulse_length.current()?;
+        let pulse = if pulse_length == 0 {
+            0.0
+        } else {
+            (floor as u32 % pulse_length) as f64 / pulse_length as f64
+        };
+        let phase = if duration <= 0.0 {
+            0.0
+        } else {
+            let offset = match data.color_pulse.current()? {
+                TrackColorPulse::Forward => -pulse,
+                TrackColorPulse::None => 0.0,
+                TrackColorPulse::Backward => pulse,
+            };
+            (seconds / duration + offset).rem_euclid(1.0)
+        };
+        Ok(track_color(
+            color_type,
+            data.color.current()?,
+            data.secondary_color.current()?,
+            phase,
+            floor,
+            data.glow_intensity.current()?,
+        ))
+    }
+}
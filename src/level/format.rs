@@ -0,0 +1,134 @@
+use serde_json_lenient::{Map, Number, Value};
+
+/// Lays out a level the way the game writes it: `angleData` and other plain values on one
+/// line, `settings` one key per line, and `actions` (and other arrays of objects such as
+/// `decorations`) one object per line with `floor` and `eventType` first.
+///
+/// Formatting the parsed JSON of a level saved by the game gives back its text without the BOM.
+pub fn format_game_style(level: &Value) -> String {
+    let Value::Object(members) = level else {
+        return to_inline_string(level);
+    };
+    let mut out = String::from("{\n");
+    for (index, (key, value)) in members.iter().enumerate() {
+        let last = index + 1 == members.len();
+        let name = key.as_str();
+        let key = Value::from(name);
+        match value {
+            Value::Object(object) => {
+                out.push_str(&format!("\t{}:\n\t{{\n", key));
+                write_object_lines(&mut out, object);
+                out.push_str(if last { "\t}\n" } else { "\t},\n" });
+            }
+            Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object) => {
+                out.push_str(&format!("\t{}:\n\t[\n", key));
+                for (index, item) in items.iter().enumerate() {
+                    out.push_str("\t\t");
+                    out.push_str(&action_to_inline_string(item));
+                    out.push_str(if index + 1 == items.len() {
+                        "\n"
+                    } else {
+                        ",\n"
+                    });
+                }
+                out.push_str(if last { "\t]\n" } else { "\t],\n" });
+            }
+            Value::Array(items) if items.is_empty() && name != "angleData" => {
+                out.push_str(&format!("\t{}:\n\t[\n", key));
+                out.push_str(if last { "\t]\n" } else { "\t],\n" });
+            }
+            _ => {
+                let separator = if last { " \n" } else { ", \n" };
+                out.push_str(&format!(
+                    "\t{}: {}{}",
+                    key,
+                    to_inline_string(value),
+                    separator
+                ));
+            }
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// The members of `settings`, one per line. The game puts a space before the first comma.
+fn write_object_lines(out: &mut String, object: &Map<String, Value>) {
+    for (index, (key, value)) in object.iter().enumerate() {
+        let separator = match index {
+            _ if index + 1 == object.len() => " \n",
+            0 => " ,\n",
+            _ => ", \n",
+        };
+        let key = Value::from(key.as_str());
+        out.push_str(&format!(
+            "\t\t{}: {}{}",
+            key,
+            to_inline_string(value),
+            separator
+        ));
+    }
+}
+
+/// Writes an action on a single line with `floor` and `eventType` first, like the game.
+pub(crate) fn action_to_inline_string(action: &Value) -> String {
+    let Value::Object(object) = action else {
+        return to_inline_string(action);
+    };
+    let mut ordered = Map::new();
+    for key in ["floor", "eventType"] {
+        if let Some(value) = object.get(key) {
+            ordered.insert(key.to_string(), value.clone());
+        }
+    }
+    for (key, value) in object {
+        if !ordered.contains_key(key) {
+            ordered.insert(key.clone(), value.clone());
+        }
+    }
+    to_inline_string(&Value::Object(ordered))
+}
+
+/// Writes `value` on a single line, with integral floats written as integers.
+pub(crate) fn to_inline_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_inline(&mut out, value);
+    out
+}
+fn write_inline(out: &mut String, value: &Value) {
+    match value {
+        Value::Number(number) => out.push_str(&number_text(number)),
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_inline(out, item);
+            }
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{ ");
+            for (index, (key, item)) in map.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push_str(": ");
+                write_inline(out, item);
+            }
+            out.push_str(" }");
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+fn number_text(number: &Number) -> String {
+    match number.as_f64() {
+        Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < 1e15 => {
+            format!("{}", float as i64)
+        }
+        _ => number.to_string(),
+    }
+}
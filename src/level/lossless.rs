@@ -0,0 +1,608 @@
+use super::{
+    LevelWriter,
+    format::{action_to_inline_string, to_inline_string},
+    migrate::migrated,
+};
+use crate::*;
+use serde_json_lenient::Value;
+use std::{collections::BTreeSet, error};
+
+/// A byte range of the source text.
+type Span = (usize, usize);
+
+#[derive(Debug, Clone)]
+enum Node {
+    Object { span: Span, members: Vec<Member> },
+    Array { span: Span, items: Vec<Node> },
+    Scalar { span: Span },
+}
+impl Node {
+    fn span(&self) -> Span {
+        match self {
+            Node::Object { span, .. } | Node::Array { span, .. } | Node::Scalar { span } => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Member {
+    key: String,
+    /// Where the key starts.
+    start: usize,
+    value: Node,
+}
+impl Member {
+    fn span(&self) -> Span {
+        (self.start, self.value.span().1)
+    }
+}
+
+/// Records the spans of a lenient JSON document (comments and trailing commas included).
+/// The document is expected to have been accepted by `serde_json_lenient` already.
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+    fn skip_trivia(&mut self) {
+        let bytes = self.text.as_bytes();
+        loop {
+            match (self.peek(), bytes.get(self.pos + 1)) {
+                (Some(b' ' | b'\t' | b'\n' | b'\r'), _) => self.pos += 1,
+                (Some(b'/'), Some(b'/')) => {
+                    self.pos = match self.text[self.pos..].find('\n') {
+                        Some(offset) => self.pos + offset,
+                        None => self.text.len(),
+                    }
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    self.pos = match self.text[self.pos + 2..].find("*/") {
+                        Some(offset) => self.pos + 2 + offset + 2,
+                        None => self.text.len(),
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+    fn string(&mut self) -> Option<Span> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.peek()? {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Some((start, self.pos));
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+    fn value(&mut self) -> Option<Node> {
+        self.skip_trivia();
+        let start = self.pos;
+        match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                let mut members = vec![];
+                loop {
+                    self.skip_trivia();
+                    match self.peek()? {
+                        b'}' => break,
+                        b',' => self.pos += 1,
+                        b'"' => {
+                            let (key_start, key_end) = self.string()?;
+                            let key = serde_json_lenient::from_str(&self.text[key_start..key_end])
+                                .ok()?;
+                            self.skip_trivia();
+                            if self.peek()? != b':' {
+                                return None;
+                            }
+                            self.pos += 1;
+                            let value = self.value()?;
+                            members.push(Member {
+                                key,
+                                start: key_start,
+                                value,
+                            });
+                        }
+                        _ => return None,
+                    }
+                }
+                self.pos += 1;
+                Some(Node::Object {
+                    span: (start, self.pos),
+                    members,
+                })
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = vec![];
+                loop {
+                    self.skip_trivia();
+                    match self.peek()? {
+                        b']' => break,
+                        b',' => self.pos += 1,
+                        _ => items.push(self.value()?),
+                    }
+                }
+                self.pos += 1;
+                Some(Node::Array {
+                    span: (start, self.pos),
+                    items,
+                })
+            }
+            b'"' => Some(Node::Scalar {
+                span: self.string()?,
+            }),
+            _ => {
+                while !matches!(
+                    self.peek(),
+                    None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r' | b'/')
+                ) {
+                    self.pos += 1;
+                }
+                (self.pos > start).then_some(Node::Scalar {
+                    span: (start, self.pos),
+                })
+            }
+        }
+    }
+}
+
+/// Replacements of byte ranges of the source text.
+#[derive(Default)]
+struct Edits(Vec<(usize, usize, String)>);
+impl Edits {
+    fn replace(&mut self, (start, end): Span, text: String) {
+        self.0.push((start, end, text));
+    }
+    fn insert(&mut self, pos: usize, text: String) {
+        self.0.push((pos, pos, text));
+    }
+    fn apply(mut self, text: &str) -> String {
+        self.0.sort_by_key(|(start, end, _)| (*start, *end));
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, replacement) in self.0 {
+            let start = start.max(cursor);
+            out.push_str(&text[cursor..start]);
+            out.push_str(&replacement);
+            cursor = end.max(start);
+        }
+        out.push_str(&text[cursor..]);
+        out
+    }
+}
+
+/// The original text of a level loaded with [`Level::from_str_lossless`], used to write the
+/// level back with only the parts that changed rewritten.
+#[derive(Debug, Clone)]
+pub(crate) struct SourceDocument {
+    text: String,
+    members: Vec<Member>,
+    /// The level as the writer serialized it right after loading.
+    baseline: Value,
+    /// The index in the source `actions` of every event, in tile order.
+    action_order: Vec<usize>,
+    decoration_order: Vec<usize>,
+    /// The `settings` keys and source `actions` and `decorations` indices changed by
+    /// migrating on load, which are written out even if unchanged since.
+    migrated_settings: BTreeSet<String>,
+    migrated_actions: BTreeSet<usize>,
+    migrated_decorations: BTreeSet<usize>,
+}
+
+impl SourceDocument {
+    pub(crate) fn new(text: String, json: &Value, level: &Level) -> Result<Self, LevelLoadError> {
+        let mut scanner = Scanner {
+            text: &text,
+            pos: if text.starts_with('\u{feff}') { 3 } else { 0 },
+        };
+        let Some(Node::Object { members, .. }) = scanner.value() else {
+            return Err(LevelLoadError::UnsupportedSyntax {
+                offset: scanner.pos,
+            });
+        };
+        let baseline = serde_json_lenient::to_value(LevelWriter {
+            level,
+            options: &angle_data_options(&SaveOptions::default()),
+        })
+        .map_err(LevelLoadError::Json)?;
+        let floors: Vec<u64> = match json.get("actions").and_then(|actions| actions.as_array()) {
+            Some(actions) => actions
+                .iter()
+                .map(|action| action["floor"].as_u64().unwrap_or_default())
+                .collect(),
+            None => vec![],
+        };
+        let mut action_order: Vec<usize> = (0..floors.len()).collect();
+        action_order.sort_by_key(|&index| floors[index]);
+        let decoration_count = json
+            .get("decorations")
+            .and_then(|decorations| decorations.as_array())
+            .map_or(0, Vec::len);
+        let decoration_order = (0..decoration_count).collect();
+        let migrated = migrated(json).0;
+        let migrated_settings = match migrated["settings"].as_object() {
+            Some(settings) => settings
+                .iter()
+                .filter(|(key, value)| json["settings"].get(key.as_str()) != Some(value))
+                .map(|(key, _)| key.clone())
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        let migrated_items = |key: &str| match migrated[key].as_array() {
+            Some(items) => items
+                .iter()
+                .enumerate()
+                .filter(|(index, item)| json[key].get(*index) != Some(item))
+                .map(|(index, _)| index)
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        let migrated_actions = migrated_items("actions");
+        let migrated_decorations = migrated_items("decorations");
+        Ok(SourceDocument {
+            text,
+            members,
+            baseline,
+            action_order,
+            decoration_order,
+            migrated_settings,
+            migrated_actions,
+            migrated_decorations,
+        })
+    }
+
+    pub(crate) fn write(
+        &self,
+        level: &Level,
+        options: &SaveOptions,
+    ) -> Result<String, Box<dyn error::Error>> {
+        let current = serde_json_lenient::to_value(LevelWriter {
+            level,
+            options: &angle_data_options(options),
+        })?;
+        let mut edits = Edits::default();
+        self.write_angles(&mut edits, level, &current, options)?;
+        self.write_settings(&mut edits, &current);
+        self.write_array(
+            &mut edits,
+            "actions",
+            &current,
+            &self.action_order,
+            &self.migrated_actions,
+        );
+        self.write_array(
+            &mut edits,
+            "decorations",
+            &current,
+            &self.decoration_order,
+            &self.migrated_decorations,
+        );
+        Ok(edits.apply(&self.text))
+    }
+
+    fn member(&self, key: &str) -> Option<&Member> {
+        self.members.iter().find(|member| member.key == key)
+    }
+
+    fn write_angles(
+        &self,
+        edits: &mut Edits,
+        level: &Level,
+        current: &Value,
+        options: &SaveOptions,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let Some(member) = self.member("angleData").or_else(|| self.member("pathData")) else {
+            return Ok(());
+        };
+        let source_is_path_data = member.key == "pathData";
+        let angle_data: Vec<f64> = level.tiles.iter().skip(1).map(|tile| tile.angle).collect();
+        // Rewriting the angles in another format would replace the whole member, so `Auto`
+        // keeps the source format here.
+        let options = match options.angle_format {
+            AngleFormat::Auto => SaveOptions {
+                angle_format: AngleFormat::Source,
+                ..*options
+            },
+            _ => *options,
+        };
+        let path_data = options.path_data(&angle_data, source_is_path_data)?;
+        let (current, baseline) = (&current["angleData"], &self.baseline["angleData"]);
+        match (path_data, &member.value) {
+            (Some(path_data), _) if source_is_path_data => {
+                if current != baseline {
+                    edits.replace(member.value.span(), Value::from(path_data).to_string());
+                }
+            }
+            (None, Node::Array { items, .. }) if !source_is_path_data => {
+                match (current.as_array(), baseline.as_array()) {
+                    (Some(current), Some(baseline)) if current.len() == items.len() => {
+                        for ((item, current), baseline) in items.iter().zip(current).zip(baseline) {
+                            if current != baseline {
+                                edits.replace(item.span(), to_inline_string(current));
+                            }
+                        }
+                    }
+                    _ => edits.replace(member.value.span(), to_inline_string(current)),
+                }
+            }
+            (Some(path_data), _) => edits.replace(
+                member.span(),
+                format!("\"pathData\": {}", Value::from(path_data)),
+            ),
+            (None, _) => edits.replace(
+                member.span(),
+                format!("\"angleData\": {}", to_inline_string(current)),
+            ),
+        }
+        Ok(())
+    }
+
+    fn write_settings(&self, edits: &mut Edits, current: &Value) {
+        let (Some(member), Some(current), Some(baseline)) = (
+            self.member("settings"),
+            current["settings"].as_object(),
+            self.baseline["settings"].as_object(),
+        ) else {
+            return;
+        };
+        let Node::Object { span, members } = &member.value else {
+            return;
+        };
+        let changed = |key: &str, value: &Value| {
+            baseline.get(key) != Some(value) || self.migrated_settings.contains(key)
+        };
+        let spans: Vec<Span> = members.iter().map(Member::span).collect();
+        let mut deleted = BTreeSet::new();
+        for (index, member) in members.iter().enumerate() {
+            match current.get(&member.key) {
+                Some(value) if changed(&member.key, value) => {
+                    edits.replace(member.value.span(), to_inline_string(value))
+                }
+                Some(_) => {}
+                None => {
+                    deleted.insert(index);
+                }
+            }
+        }
+        self.delete(edits, &spans, &deleted);
+        let added: Vec<String> = current
+            .iter()
+            // Defaults filled in for keys the source lacks are only written once they change.
+            .filter(|(key, value)| {
+                !members.iter().any(|member| &member.key == *key) && changed(key, value)
+            })
+            .map(|(key, value)| {
+                format!("{}: {}", Value::from(key.as_str()), to_inline_string(value))
+            })
+            .collect();
+        self.insert(edits, *span, &spans, spans.len().checked_sub(1), added);
+    }
+
+    /// Writes the `actions` or `decorations` array. `order` maps the items in level order to
+    /// their index in the source, and the `forced` source items are rewritten even if unchanged.
+    fn write_array(
+        &self,
+        edits: &mut Edits,
+        key: &str,
+        current: &Value,
+        order: &[usize],
+        forced: &BTreeSet<usize>,
+    ) {
+        let current = current[key].as_array().map(Vec::as_slice).unwrap_or_default();
+        let baseline = self.baseline[key]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let Some(member) = self.member(key) else {
+            if !current.is_empty() {
+                let spans: Vec<Span> = self.members.iter().map(Member::span).collect();
+                let items: Vec<String> = current.iter().map(action_to_inline_string).collect();
+                let items = format!(
+                    "{}: [\n\t\t{}\n\t]",
+                    Value::from(key),
+                    items.join(",\n\t\t")
+                );
+                self.insert(
+                    edits,
+                    (0, 0),
+                    &spans,
+                    spans.len().checked_sub(1),
+                    vec![items],
+                );
+            }
+            return;
+        };
+        let Node::Array { span, items } = &member.value else {
+            return;
+        };
+        let spans: Vec<Span> = items.iter().map(Node::span).collect();
+        let source_span = |index: usize| spans[order[index]];
+        // Between two unchanged events, changed events replace the removed ones in order and
+        // the rest are deleted or inserted after the last kept one.
+        let mut deleted = BTreeSet::new();
+        let mut previous = None;
+        let (mut current_start, mut baseline_start) = (0, 0);
+        let end = (current.len(), baseline.len());
+        for (current_end, baseline_end) in align(current, baseline).into_iter().chain([end]) {
+            if current_end < current.len() {
+                let index = order[baseline_end];
+                if forced.contains(&index) {
+                    edits.replace(spans[index], action_to_inline_string(&current[current_end]));
+                }
+            }
+            let added = &current[current_start..current_end];
+            let removed = baseline_start..baseline_end;
+            let paired = added.len().min(removed.len());
+            for (index, value) in added.iter().take(paired).enumerate() {
+                edits.replace(
+                    source_span(baseline_start + index),
+                    action_to_inline_string(value),
+                );
+            }
+            deleted.extend(removed.skip(paired).map(|index| order[index]));
+            let inserted: Vec<String> = added[paired..]
+                .iter()
+                .map(action_to_inline_string)
+                .collect();
+            if paired > 0 {
+                previous = Some(baseline_start + paired - 1);
+            }
+            match previous {
+                Some(previous) => {
+                    let after = Some(order[previous]);
+                    self.insert(edits, *span, &spans, after, inserted);
+                }
+                None if baseline.is_empty() => self.insert(edits, *span, &spans, None, inserted),
+                None if !inserted.is_empty() => {
+                    let next = source_span(baseline_start + paired).0;
+                    let indent = self.indent_before(next);
+                    let text: String = inserted
+                        .iter()
+                        .map(|text| format!("{text},{indent}"))
+                        .collect();
+                    edits.insert(next, text);
+                }
+                None => {}
+            }
+            previous = Some(baseline_end);
+            current_start = current_end + 1;
+            baseline_start = baseline_end + 1;
+        }
+        self.delete(edits, &spans, &deleted);
+    }
+
+    /// The whitespace before `pos`, used to lay out inserted items like their neighbours.
+    fn indent_before(&self, pos: usize) -> &str {
+        let start = self.text[..pos]
+            .trim_end_matches([' ', '\t', '\n', '\r'])
+            .len();
+        match &self.text[start..pos] {
+            "" => " ",
+            indent => indent,
+        }
+    }
+
+    /// Inserts `texts` after item `after`, or into the empty container at `span`.
+    fn insert(
+        &self,
+        edits: &mut Edits,
+        span: Span,
+        spans: &[Span],
+        after: Option<usize>,
+        texts: Vec<String>,
+    ) {
+        if texts.is_empty() {
+            return;
+        }
+        match after {
+            Some(after) => {
+                let indent = self.indent_before(spans[after].0);
+                let text: String = texts
+                    .iter()
+                    .map(|text| format!(",{indent}{text}"))
+                    .collect();
+                edits.insert(spans[after].1, text);
+            }
+            None => {
+                let (open, close) = (
+                    &self.text[span.0..span.0 + 1],
+                    &self.text[span.1 - 1..span.1],
+                );
+                edits.replace(
+                    span,
+                    format!("{open}\n\t\t{}\n\t{close}", texts.join(",\n\t\t")),
+                );
+            }
+        }
+    }
+
+    /// Removes the items at `deleted` together with one separating comma.
+    fn delete(&self, edits: &mut Edits, spans: &[Span], deleted: &BTreeSet<usize>) {
+        let mut deleted = deleted.iter().copied().peekable();
+        while let Some(first) = deleted.next() {
+            let mut last = first;
+            while deleted.next_if_eq(&(last + 1)).is_some() {
+                last += 1;
+            }
+            let range = match (spans.get(last + 1), first.checked_sub(1)) {
+                (Some(next), _) => (spans[first].0, next.0),
+                (None, Some(previous)) => (spans[previous].1, spans[last].1),
+                (None, None) => (spans[first].0, spans[last].1),
+            };
+            edits.replace(range, String::new());
+        }
+    }
+}
+
+/// Above this many comparisons, changed events are paired by position only.
+const MAX_ALIGNMENT_CELLS: usize = 1 << 20;
+
+/// The unchanged events as (current, baseline) index pairs, in order: the common prefix and
+/// suffix, and the longest common subsequence of what is in between.
+pub(super) fn align(current: &[Value], baseline: &[Value]) -> Vec<(usize, usize)> {
+    let prefix = current
+        .iter()
+        .zip(baseline)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = current[prefix..]
+        .iter()
+        .rev()
+        .zip(baseline[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut matches: Vec<(usize, usize)> = (0..prefix).map(|index| (index, index)).collect();
+    let middle = (
+        &current[prefix..current.len() - suffix],
+        &baseline[prefix..baseline.len() - suffix],
+    );
+    if middle.0.len() * middle.1.len() <= MAX_ALIGNMENT_CELLS {
+        let (current, baseline) = middle;
+        let width = baseline.len() + 1;
+        let mut lengths = vec![0u32; (current.len() + 1) * width];
+        for i in (0..current.len()).rev() {
+            for j in (0..baseline.len()).rev() {
+                lengths[i * width + j] = if current[i] == baseline[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < current.len() && j < baseline.len() {
+            if current[i] == baseline[j] {
+                matches.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    matches.extend((0..suffix).map(|index| {
+        (
+            current.len() - suffix + index,
+            baseline.len() - suffix + index,
+        )
+    }));
+    matches
+}
+
+/// `options` with every angle written to `angleData`, so that angles compare numerically.
+fn angle_data_options(options: &SaveOptions) -> SaveOptions {
+    SaveOptions {
+        angle_format: AngleFormat::AngleData,
+        ..*options
+    }
+}
@@ -0,0 +1,199 @@
+use serde_json_lenient::{Map, Value};
+use std::borrow::Cow;
+
+/// The newest level format version the model follows.
+pub const LATEST_VERSION: u32 = 15;
+
+/// A step that upgrades the JSON of a level older than `version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+}
+
+struct Step {
+    migration: Migration,
+    /// Rewrites the settings and the actions and decorations of a level.
+    apply: fn(&mut Map<String, Value>, &mut [Value]),
+}
+
+const STEPS: [Step; 7] = [
+    Step {
+        migration: Migration {
+            version: 2,
+            description: "\"Enabled\" and \"Disabled\" are written as booleans",
+        },
+        apply: enabled_to_bool,
+    },
+    Step {
+        migration: Migration {
+            version: 4,
+            description: "tile ranges are [index, relativeTo]",
+        },
+        apply: track_ranges,
+    },
+    Step {
+        migration: Migration {
+            version: 4,
+            description: "MoveTrack has positionOffset",
+        },
+        apply: move_track_position_offset,
+    },
+    Step {
+        migration: Migration {
+            version: 6,
+            description: "legacyFlash keeps the old Flash behaviour",
+        },
+        apply: |settings, _| legacy_flag(settings, "legacyFlash"),
+    },
+    Step {
+        migration: Migration {
+            version: 9,
+            description: "legacyCamRelativeTo keeps the old camera relativeTo behaviour",
+        },
+        apply: |settings, _| legacy_flag(settings, "legacyCamRelativeTo"),
+    },
+    Step {
+        migration: Migration {
+            version: 13,
+            description: "legacySpriteTiles keeps the old tile sprites",
+        },
+        apply: |settings, _| legacy_flag(settings, "legacySpriteTiles"),
+    },
+    Step {
+        migration: Migration {
+            version: LATEST_VERSION,
+            description: "legacyTween keeps the old tween behaviour",
+        },
+        apply: |settings, _| legacy_flag(settings, "legacyTween"),
+    },
+];
+
+/// The boolean keys of the settings, actions and decorations, which older levels wrote as
+/// "Enabled" or "Disabled".
+const BOOL_KEYS: [&str; 23] = [
+    "separateCountdownTime",
+    "seizureWarning",
+    "imageSmoothing",
+    "lockRot",
+    "loopBG",
+    "pulseOnFloor",
+    "showDefaultBGIfNoImage",
+    "showDefaultBGTile",
+    "loopVideo",
+    "floorIconOutlines",
+    "stickToFloors",
+    "legacyFlash",
+    "legacyCamRelativeTo",
+    "legacySpriteTiles",
+    "legacyTween",
+    "disableV15Features",
+    "justThisTile",
+    "editorOnly",
+    "enabled",
+    "disableOthers",
+    "fadeOut",
+    "lockRotation",
+    "lockScale",
+];
+
+/// Upgrades the JSON of a level to [`LATEST_VERSION`] and returns the migrations applied,
+/// oldest first. `settings.version` becomes [`LATEST_VERSION`] if any migration applied, so
+/// that the level is saved in the version its content follows.
+///
+/// Levels without a numeric `settings.version` or already at the latest version are left
+/// untouched.
+pub fn migrate(json: &mut Value) -> Vec<Migration> {
+    let Some(version) = version(json) else {
+        return vec![];
+    };
+    let Some(object) = json.as_object_mut() else {
+        return vec![];
+    };
+    let mut items = vec![];
+    let mut counts = vec![];
+    for key in ["actions", "decorations"] {
+        match object.get_mut(key) {
+            Some(Value::Array(array)) => {
+                counts.push(array.len());
+                items.append(array);
+            }
+            _ => counts.push(0),
+        }
+    }
+    let mut applied = vec![];
+    if let Some(Value::Object(settings)) = object.get_mut("settings") {
+        for step in STEPS.iter().filter(|step| version < step.migration.version) {
+            (step.apply)(settings, &mut items);
+            applied.push(step.migration);
+        }
+        if !applied.is_empty() {
+            settings.insert("version".to_string(), Value::from(LATEST_VERSION));
+        }
+    }
+    let mut items = items.into_iter();
+    for (key, count) in ["actions", "decorations"].into_iter().zip(counts) {
+        if let Some(Value::Array(array)) = object.get_mut(key) {
+            array.extend(items.by_ref().take(count));
+        }
+    }
+    applied
+}
+
+/// The migrated JSON of a level, borrowed if no migration applies.
+pub(crate) fn migrated(json: &Value) -> (Cow<'_, Value>, Vec<Migration>) {
+    match version(json) {
+        Some(version) if version < LATEST_VERSION => {
+            let mut json = json.clone();
+            let migrations = migrate(&mut json);
+            (Cow::Owned(json), migrations)
+        }
+        _ => (Cow::Borrowed(json), vec![]),
+    }
+}
+
+fn version(json: &Value) -> Option<u32> {
+    let version = json.get("settings")?.get("version")?.as_u64()?;
+    Some(version.min(u32::MAX as u64) as u32)
+}
+
+fn enabled_to_bool(settings: &mut Map<String, Value>, items: &mut [Value]) {
+    let objects = items.iter_mut().filter_map(Value::as_object_mut);
+    for object in [settings].into_iter().chain(objects) {
+        for (key, value) in object.iter_mut() {
+            if !BOOL_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            match value.as_str() {
+                Some("Enabled") => *value = Value::Bool(true),
+                Some("Disabled") => *value = Value::Bool(false),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn track_ranges(_: &mut Map<String, Value>, items: &mut [Value]) {
+    for action in items.iter_mut().filter_map(Value::as_object_mut) {
+        for key in ["startTile", "endTile"] {
+            if let Some(index) = action.get_mut(key).filter(|index| index.is_number()) {
+                *index = Value::Array(vec![index.take(), Value::from("ThisTile")]);
+            }
+        }
+    }
+}
+
+fn move_track_position_offset(_: &mut Map<String, Value>, items: &mut [Value]) {
+    for action in items.iter_mut().filter_map(Value::as_object_mut) {
+        if action.get("eventType").and_then(Value::as_str) == Some("MoveTrack") {
+            action
+                .entry("positionOffset")
+                .or_insert(Value::Array(vec![Value::Null, Value::Null]));
+        }
+    }
+}
+
+/// Older levels were made for the old behaviour, so the flag is on unless the level sets it.
+fn legacy_flag(settings: &mut Map<String, Value>, key: &str) {
+    settings.entry(key).or_insert(Value::Bool(true));
+}
@@ -1,9 +1,24 @@
+mod animation;
+mod color;
+mod format;
+pub use format::format_game_style;
+mod lossless;
+use lossless::SourceDocument;
+mod migrate;
+pub use migrate::{LATEST_VERSION, Migration, migrate};
 mod parse;
+pub use parse::ParseDiagnostic;
 mod serde_level;
+pub use serde_level::{AngleFormat, LevelLoadError, OutputFormat, SaveOptions};
+use serde_level::LevelWriter;
+mod shape;
+use shape::SourceShape;
+mod tempo;
+pub use tempo::*;
 mod update;
 use event::*;
 
-use std::{error, fmt, fs, path::Path};
+use std::{borrow::Cow, error, fmt, fs, io, path::Path, str};
 
 use crate::*;
 use getset::*;
@@ -30,7 +45,7 @@ pub enum HitMargin {
     TooEarly,
 }
 
-pub const PATH_ANGLE: [(char, f64); 29] = [
+pub const PATH_ANGLE: [(char, f64); 25] = [
     ('R', 0.0),
     ('p', 15.0),
     ('J', 30.0),
@@ -55,27 +70,126 @@ pub const PATH_ANGLE: [(char, f64); 29] = [
     ('C', 315.0),
     ('M', 330.0),
     ('A', 345.0),
-    ('5', 555.0),
-    ('6', 666.0),
-    ('7', 777.0),
-    ('8', 888.0),
     ('!', 999.0),
 ];
-pub fn path2angle(path: char) -> Result<f64, ()> {
-    for (cmp_path, angle) in PATH_ANGLE {
-        if path == cmp_path {
-            return Ok(angle);
+/// pathData characters whose angle is the inner angle to the previous tile rather than a
+/// direction, e.g. '5' turns by the inner angle of a pentagon.
+pub const RELATIVE_PATH_ANGLE: [(char, f64); 8] = [
+    ('5', 108.0),
+    ('6', 252.0),
+    ('7', 900.0 / 7.0),
+    ('8', 360.0 - 900.0 / 7.0),
+    ('t', 60.0),
+    ('h', 120.0),
+    ('j', 240.0),
+    ('y', 300.0),
+];
+/// Default tolerance, in degrees, when looking up the pathData character of an angle.
+pub const DEFAULT_ANGLE_EPSILON: f64 = 1e-4;
+
+/// The distance between two angles in degrees, going the short way around.
+fn angle_distance(a: f64, b: f64) -> f64 {
+    let distance = (a - b).rem_euclid(360.0);
+    distance.min(360.0 - distance)
+}
+
+pub fn path2angle(path: char) -> Option<f64> {
+    PATH_ANGLE
+        .iter()
+        .find(|(cmp_path, _)| path == *cmp_path)
+        .map(|(_, angle)| *angle)
+}
+/// The pathData character of `angle`, allowing for `epsilon` degrees of floating-point noise.
+pub fn angle2path(angle: f64, epsilon: f64) -> Option<char> {
+    PATH_ANGLE
+        .iter()
+        .find(|(_, cmp_angle)| {
+            if *cmp_angle == 999.0 || angle == 999.0 {
+                (angle - cmp_angle).abs() <= epsilon
+            } else {
+                angle_distance(angle, *cmp_angle) <= epsilon
+            }
+        })
+        .map(|(path, _)| *path)
+}
+pub fn relative_path2angle(path: char) -> Option<f64> {
+    RELATIVE_PATH_ANGLE
+        .iter()
+        .find(|(cmp_path, _)| path == *cmp_path)
+        .map(|(_, angle)| *angle)
+}
+/// The sign of the angle a planet turns by in `orbit`.
+fn orbit_sign(orbit: Orbit) -> f64 {
+    match orbit {
+        Orbit::Clockwise => -1.0,
+        Orbit::Anticlockwise => 1.0,
+    }
+}
+pub fn relative_angle2path(relative_angle: f64, epsilon: f64) -> Option<char> {
+    RELATIVE_PATH_ANGLE
+        .iter()
+        .find(|(_, cmp_angle)| angle_distance(relative_angle, *cmp_angle) <= epsilon)
+        .map(|(path, _)| *path)
+}
+
+/// Tracks the direction relative pathData characters are measured from.
+#[derive(Debug, Clone, Copy, Default)]
+struct PathCursor {
+    last_angle: f64,
+    midspins: usize,
+}
+impl PathCursor {
+    /// The direction a relative angle of 0 points to.
+    fn reference(&self) -> f64 {
+        if self.midspins % 2 == 1 {
+            self.last_angle
+        } else {
+            self.last_angle + 180.0
+        }
+    }
+    fn push(&mut self, angle: f64) {
+        if angle == 999.0 {
+            self.midspins += 1;
+        } else {
+            self.last_angle = angle;
+            self.midspins = 0;
+        }
+    }
+}
+
+/// Converts pathData to absolute angles. On failure returns the index of the unknown character.
+pub fn path_data2angles(path_data: &str) -> Result<Vec<f64>, (usize, char)> {
+    let mut cursor = PathCursor::default();
+    let mut angles = vec![];
+    for (index, path) in path_data.chars().enumerate() {
+        let angle = match (path2angle(path), relative_path2angle(path)) {
+            (Some(angle), _) => angle,
+            (None, Some(relative_angle)) => (cursor.reference() - relative_angle).rem_euclid(360.0),
+            (None, None) => return Err((index, path)),
         };
+        cursor.push(angle);
+        angles.push(angle);
     }
-    Err(())
+    Ok(angles)
 }
-pub fn angle2path(angle: f64) -> Result<char, ()> {
-    for (path, cmp_angle) in PATH_ANGLE {
-        if angle == cmp_angle {
-            return Ok(path);
+/// Converts absolute angles to pathData, using relative characters for angles that have no
+/// absolute one. On failure returns the index of the first angle that can't be represented.
+pub fn angles2path_data(angles: &[f64], epsilon: f64) -> Result<String, usize> {
+    let mut cursor = PathCursor::default();
+    let mut path_data = String::with_capacity(angles.len());
+    for (index, &angle) in angles.iter().enumerate() {
+        let path = angle2path(angle, epsilon)
+            .or_else(|| relative_angle2path(cursor.reference() - angle, epsilon))
+            .ok_or(index)?;
+        // Continue from the angle the character stands for, so noise doesn't add up.
+        let angle = match path2angle(path) {
+            Some(angle) => angle,
+            None => (cursor.reference() - relative_path2angle(path).unwrap()).rem_euclid(360.0),
         };
+        cursor.push(angle);
+        path_data.push(path);
     }
-    Err(())
+    Ok(path_data)
 }
 
 #[derive(Debug)]
@@ -93,6 +207,23 @@ impl fmt::Display for LevelIsNotParsedError {
 }
 impl error::Error for LevelIsNotParsedError {}
 
+/// Returned when a floor past the last tile is asked for.
+#[derive(Debug)]
+pub struct FloorOutOfRangeError {
+    pub floor: usize,
+    pub tile_count: usize,
+}
+impl fmt::Display for FloorOutOfRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Floor {} is out of range, the level has {} tiles.",
+            self.floor, self.tile_count
+        )
+    }
+}
+impl error::Error for FloorOutOfRangeError {}
+
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum RelativeToCamera {
     Tile,
@@ -102,28 +233,96 @@ pub enum RelativeToCamera {
     LastPosition,
 }
 
-const fn f64_1() -> f64 {
-    1.0
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SpecialArtistType {
+    #[default]
+    None,
+    AuthorIsArtist,
+    PublicLicense,
 }
 
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BgDisplayMode {
+    #[default]
+    FitToScreen,
+    Unscaled,
+    Tiled,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BgShapeType {
+    #[default]
+    Default,
+    SingleColor,
+    Disabled,
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EasePartBehavior {
+    #[default]
+    Mirror,
+    Repeat,
+}
+
+/// The `settings` block of a level, in the order the game writes it.
+/// Keys missing from older levels fall back to the game's defaults.
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Settings {
     pub version: u32,
     pub artist: String,
+    #[serde(default)]
+    pub special_artist_type: SpecialArtistType,
+    #[serde(default)]
+    pub artist_permission: String,
     pub song: String,
     pub author: String,
     #[serde(deserialize_with = "de_bool")]
     pub separate_countdown_time: bool,
+    #[serde(default)]
+    pub preview_image: String,
+    #[serde(default)]
+    pub preview_icon: String,
+    #[serde(
+        default = "rgba_003f52",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub preview_icon_color: Rgba<u8>,
+    /// The preview song file of old levels; newer ones preview `songFilename`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_song: Option<String>,
+    /// In seconds.
+    #[serde(default)]
+    pub preview_song_start: f64,
+    /// In seconds.
+    #[serde(default = "f64_10")]
+    pub preview_song_duration: f64,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub seizure_warning: bool,
+    #[serde(default)]
+    pub level_desc: String,
+    /// Comma-separated.
+    #[serde(default)]
+    pub level_tags: String,
+    #[serde(default)]
+    pub artist_links: String,
+    #[serde(default)]
+    pub speed_trial_aim: f64,
+    #[serde(default = "u32_1")]
+    pub difficulty: u32,
+    #[serde(default)]
+    pub required_mods: Vec<String>,
     pub song_filename: String,
     pub bpm: f64,
     pub volume: f64,
     pub offset: f64,
     pub pitch: f64,
-    #[serde(default)]
+    pub hitsound: Hitsound,
+    #[serde(default = "f64_100")]
+    pub hitsound_volume: f64,
+    #[serde(default = "u32_4")]
     pub countdown_ticks: u32,
-    #[serde(deserialize_with = "de_bool")]
-    pub stick_to_floors: bool,
 
     pub track_color_type: TrackColorType,
     #[serde(
@@ -144,18 +343,72 @@ pub struct Settings {
     pub track_texture: String,
     #[serde(default = "f64_1")]
     pub track_texture_scale: f64,
-    #[serde(default)]
+    #[serde(default = "f64_100")]
     pub track_glow_intensity: f64,
     pub track_animation: TrackAnimation,
     pub beats_ahead: f64,
     pub track_disappear_animation: TrackDisappearAnimation,
     pub beats_behind: f64,
+
     #[serde(
         serialize_with = "ser_rgba_u8",
         deserialize_with = "de_rgba_u8"
     )]
     pub background_color: Rgba<u8>,
+    #[serde(
+        rename = "showDefaultBGIfNoImage",
+        default = "bool_true",
+        deserialize_with = "de_bool"
+    )]
+    pub show_default_bg_if_no_image: bool,
+    #[serde(
+        rename = "showDefaultBGTile",
+        default = "bool_true",
+        deserialize_with = "de_bool"
+    )]
+    pub show_default_bg_tile: bool,
+    #[serde(
+        rename = "defaultBGTileColor",
+        default = "rgba_101121",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub default_bg_tile_color: Rgba<u8>,
+    #[serde(rename = "defaultBGShapeType", default)]
+    pub default_bg_shape_type: BgShapeType,
+    #[serde(
+        rename = "defaultBGShapeColor",
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub default_bg_shape_color: Rgba<u8>,
+    #[serde(default)]
+    pub bg_image: String,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub bg_image_color: Rgba<u8>,
+    #[serde(
+        default = "vector2d_100",
+        serialize_with = "ser_vector2d_f64",
+        deserialize_with = "de_vector2d_f64"
+    )]
+    pub parallax: Vector2D<f64>,
+    #[serde(default)]
+    pub bg_display_mode: BgDisplayMode,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub image_smoothing: bool,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub lock_rot: bool,
+    #[serde(rename = "loopBG", default, deserialize_with = "de_bool")]
+    pub loop_bg: bool,
+    #[serde(default = "f64_100")]
+    pub scaling_ratio: f64,
 
+    pub relative_to: RelativeToCamera,
     #[serde(
         default,
         serialize_with = "ser_vector2d_f64",
@@ -164,14 +417,105 @@ pub struct Settings {
     pub position: Vector2D<f64>,
     pub rotation: f64,
     pub zoom: f64,
-    pub relative_to: RelativeToCamera,
+    #[serde(default = "bool_true", deserialize_with = "de_bool")]
+    pub pulse_on_floor: bool,
 
-    pub hitsound: Hitsound,
-    #[serde(default="f64_100")]
-    pub hitsound_volume: f64,
+    #[serde(default)]
+    pub bg_video: String,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub loop_video: bool,
+    /// In milliseconds.
+    #[serde(default)]
+    pub vid_offset: f64,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub floor_icon_outlines: bool,
+    #[serde(deserialize_with = "de_bool")]
+    pub stick_to_floors: bool,
+    #[serde(default)]
+    pub planet_ease: Easing,
+    #[serde(default = "u32_1")]
+    pub planet_ease_parts: u32,
+    #[serde(default)]
+    pub planet_ease_part_behavior: EasePartBehavior,
+    #[serde(default)]
+    pub custom_class: String,
+    #[serde(
+        default = "rgba_ffffff",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub default_text_color: Rgba<u8>,
+    #[serde(
+        default = "rgba_00000050",
+        serialize_with = "ser_rgba_u8",
+        deserialize_with = "de_rgba_u8"
+    )]
+    pub default_text_shadow_color: Rgba<u8>,
+    #[serde(default)]
+    pub congrats_text: String,
+    #[serde(default)]
+    pub perfect_text: String,
+
+    #[serde(default, deserialize_with = "de_bool")]
+    pub legacy_flash: bool,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub legacy_cam_relative_to: bool,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub legacy_sprite_tiles: bool,
+    #[serde(default, deserialize_with = "de_bool")]
+    pub legacy_tween: bool,
+    #[serde(rename = "disableV15Features", default, deserialize_with = "de_bool")]
+    pub disable_v15_features: bool,
+
+    /// Keys not modelled above, kept in their original order and written back on save.
+    #[serde(flatten)]
+    pub extra: serde_json_lenient::Map<String, serde_json_lenient::Value>,
+}
+
+/// The countdown and result texts, changed by SetDefaultText.
+#[derive(Debug, Default, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct DefaultText {
+    pub(crate) color: DynamicValue<Rgba<u8>>,
+    pub(crate) shadow_color: DynamicValue<Rgba<u8>>,
+    pub(crate) congrats_text: DynamicValue<String>,
+    pub(crate) perfect_text: DynamicValue<String>,
+}
+impl DefaultText {
+    pub(crate) fn orig2now(&mut self) {
+        self.color.orig2now();
+        self.shadow_color.orig2now();
+        self.congrats_text.orig2now();
+        self.perfect_text.orig2now();
+    }
+}
+
+/// The backdrop behind the level, changed by CustomBackground.
+#[derive(Debug, Default, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct Background {
+    pub(crate) color: DynamicValue<Rgba<u8>>,
+    pub(crate) image: DynamicValue<String>,
+    pub(crate) image_color: DynamicValue<Rgba<u8>>,
+    pub(crate) parallax: DynamicValue<Vector2D<f64>>,
+    pub(crate) display_mode: DynamicValue<BgDisplayMode>,
+    pub(crate) image_smoothing: DynamicValue<bool>,
+    pub(crate) lock_rot: DynamicValue<bool>,
+    pub(crate) loop_bg: DynamicValue<bool>,
+    pub(crate) scaling_ratio: DynamicValue<f64>,
 }
-const fn f64_100() -> f64 {
-    100.0
+impl Background {
+    pub(crate) fn orig2now(&mut self) {
+        self.color.orig2now();
+        self.image.orig2now();
+        self.image_color.orig2now();
+        self.parallax.orig2now();
+        self.display_mode.orig2now();
+        self.image_smoothing.orig2now();
+        self.lock_rot.orig2now();
+        self.loop_bg.orig2now();
+        self.scaling_ratio.orig2now();
+    }
 }
 
 #[derive(Debug, Getters)]
@@ -209,11 +553,29 @@ impl Default for Camera {
 pub struct Level {
     pub tiles: Vec<Tile>,
     pub settings: Settings,
+    pub decorations: Vec<DecorationData>,
     #[getset(get = "pub")]
     parsed: bool,
     #[getset(get = "pub")]
     camera: Camera,
+    #[getset(get = "pub")]
+    pub(crate) default_text: DefaultText,
+    #[getset(get = "pub")]
+    pub(crate) screen_effects: ScreenEffects,
+    #[getset(get = "pub")]
+    pub(crate) background: Background,
     dynamic_events: Vec<EventData>,
+    #[getset(get = "pub")]
+    tempo_map: TempoMap,
+    playback_rate: Option<f64>,
+    max_vfx: Option<bool>,
+    #[getset(get = "pub")]
+    diagnostics: Vec<ParseDiagnostic>,
+    /// The migrations applied on load to bring an older level up to [`LATEST_VERSION`].
+    #[getset(get = "pub")]
+    migrations: Vec<Migration>,
+    source: Option<SourceDocument>,
+    shape: Option<SourceShape>,
 }
 
 impl Level {
@@ -224,79 +586,144 @@ impl Level {
             ..Default::default()
         }
     }
-    pub fn open<P>(path: P) -> Result<Level, Box<dyn error::Error>>
+    pub fn open<P>(path: P) -> Result<Level, LevelLoadError>
     where
         P: AsRef<Path>,
     {
-        let string_bom = fs::read_to_string(path).unwrap();
-        let string_data = string_bom.strip_bom();
-        let level: Level = serde_json_lenient::from_str(string_data)?;
+        Level::from_bytes(&fs::read(path).map_err(LevelLoadError::Io)?)
+    }
+    pub fn from_reader<R>(mut reader: R) -> Result<Level, LevelLoadError>
+    where
+        R: io::Read,
+    {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).map_err(LevelLoadError::Io)?;
+        Level::from_bytes(&bytes)
+    }
+    /// Decodes UTF-8 (with or without a BOM) and UTF-16 (LE or BE, detected by the BOM or by
+    /// the zero byte of the leading ASCII character) level data.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Level, LevelLoadError> {
+        decode(bytes)?.parse()
+    }
+    /// Like [`Level::open`], but keeps the original document so that saving only rewrites
+    /// what was changed through the API. Key order, number literals, comments and trailing
+    /// commas of everything else are written back as they were.
+    pub fn open_lossless<P>(path: P) -> Result<Level, LevelLoadError>
+    where
+        P: AsRef<Path>,
+    {
+        Level::from_bytes_lossless(&fs::read(path).map_err(LevelLoadError::Io)?)
+    }
+    /// Like [`Level::from_bytes`], but keeps the original document, see [`Level::open_lossless`].
+    pub fn from_bytes_lossless(bytes: &[u8]) -> Result<Level, LevelLoadError> {
+        Level::from_str_lossless(&decode(bytes)?)
+    }
+    /// Like [`str::parse`], but keeps the original document, see [`Level::open_lossless`].
+    pub fn from_str_lossless(s: &str) -> Result<Level, LevelLoadError> {
+        let json = serde_json_lenient::from_str(s.strip_bom()).map_err(LevelLoadError::Json)?;
+        let mut level = Level::from_json(&json)?;
+        level.source = Some(SourceDocument::new(s.to_string(), &json, &level)?);
         Ok(level)
     }
+    /// Whether the level keeps its original document for saving.
+    pub fn is_lossless(&self) -> bool {
+        self.source.is_some()
+    }
+    pub fn save<P>(&self, path: P) -> Result<(), Box<dyn error::Error>>
+    where
+        P: AsRef<Path>,
+    {
+        self.save_with(path, &SaveOptions::default())
+    }
+    pub fn save_with<P>(&self, path: P, options: &SaveOptions) -> Result<(), Box<dyn error::Error>>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_string_with(options)?)?;
+        Ok(())
+    }
+    pub fn to_string(&self) -> Result<String, Box<dyn error::Error>> {
+        self.to_string_with(&SaveOptions::default())
+    }
+    /// Serializes the level. A level loaded losslessly is written back over its original
+    /// document whatever the output format, with [`AngleFormat::Auto`] acting like
+    /// [`AngleFormat::Source`] and changed actions laid out like the game does. Other loaded levels keep
+    /// the key order of their source and leave out the keys it lacked until they change.
+    pub fn to_string_with(&self, options: &SaveOptions) -> Result<String, Box<dyn error::Error>> {
+        if let Some(source) = &self.source {
+            return source.write(self, options);
+        }
+        let writer = LevelWriter {
+            level: self,
+            options,
+        };
+        let mut value = serde_json_lenient::to_value(&writer)?;
+        if let Some(shape) = &self.shape {
+            value = shape.apply(&value);
+        }
+        Ok(match options.output_format {
+            OutputFormat::Pretty => serde_json_lenient::to_string_pretty(&value)?,
+            OutputFormat::Game => format!("\u{feff}{}", format_game_style(&value)),
+        })
+    }
+    /// The practice-mode playback rate (1.0 by default).
+    pub fn playback_rate(&self) -> f64 {
+        self.playback_rate.unwrap_or(1.0)
+    }
+    /// Sets the practice-mode playback rate. The level has to be parsed again afterwards.
+    pub fn set_playback_rate(&mut self, playback_rate: f64) {
+        self.playback_rate = Some(playback_rate);
+        self.parsed = false;
+    }
+    /// Whether the visual effects are at their maximum (true by default), which
+    /// `maxVfxOnly` events need to run.
+    pub fn max_vfx(&self) -> bool {
+        self.max_vfx.unwrap_or(true)
+    }
+    pub fn set_max_vfx(&mut self, max_vfx: bool) {
+        self.max_vfx = Some(max_vfx);
+    }
+    /// The speed the song is played at: the song pitch times the playback rate.
+    ///
+    /// All seconds and BPMs of a parsed level are in playback time at this speed,
+    /// so they line up with the audio as it is heard.
+    pub fn speed(&self) -> f64 {
+        let pitch = if self.settings.pitch.is_finite() && self.settings.pitch > 0.0 {
+            self.settings.pitch
+        } else {
+            100.0
+        };
+        let playback_rate = self.playback_rate();
+        let playback_rate = if playback_rate.is_finite() && playback_rate > 0.0 {
+            playback_rate
+        } else {
+            1.0
+        };
+        pitch / 100.0 * playback_rate
+    }
+    /// Converts a position in the song file to playback seconds.
+    pub fn song_seconds2seconds(&self, song_seconds: f64) -> f64 {
+        song_seconds / self.speed()
+    }
+    /// Converts playback seconds to a position in the song file.
+    pub fn seconds2song_seconds(&self, seconds: f64) -> f64 {
+        seconds * self.speed()
+    }
     pub fn beats2seconds(&self, beats: f64) -> Result<f64, Box<dyn error::Error>> {
         if !self.parsed {
             return Err(Box::new(LevelIsNotParsedError {
                 calling_function: "beats2seconds",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        let mut last_beats = 0.0;
-        let mut seconds = self.settings.offset / 1000.0;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats_option,
-                    ..
-                } = *event
-                {
-                    let ss_beats = ss_beats_option.ok_or(DynamicValueEmptyError)?;
-                    if beats > ss_beats {
-                        seconds += bpm2crotchet(bpm) * (ss_beats - last_beats);
-                    } else {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                    last_beats = ss_beats;
-                }
-            }
-        }
-        seconds += bpm2crotchet(bpm) * (beats - last_beats);
-        Ok(seconds)
+        Ok(self.tempo_map.beats2seconds(beats))
     }
-    pub fn seconds2beats(&self, mut seconds: f64) -> Result<f64, Box<dyn error::Error>> {
+    pub fn seconds2beats(&self, seconds: f64) -> Result<f64, Box<dyn error::Error>> {
         if !self.parsed {
             return Err(Box::new(LevelIsNotParsedError {
                 calling_function: "seconds2beats",
             }));
         }
-        seconds -= self.settings.offset / 1000.0;
-        let mut bpm = self.settings.bpm;
-        let mut last_beats = 0.0;
-        let mut beats = 0.0;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats_option,
-                    ..
-                } = *event
-                {
-                    let ss_beats = ss_beats_option.ok_or(DynamicValueEmptyError)?;
-                    let gap_seconds = bpm2crotchet(bpm) * (ss_beats - last_beats);
-                    if seconds > gap_seconds {
-                        seconds -= gap_seconds;
-                    } else {
-                        break 'tile_loop;
-                    }
-                    beats += ss_beats - last_beats;
-                    bpm = set_speed.get_bpm(bpm);
-                    last_beats = ss_beats;
-                }
-            }
-        }
-        beats += seconds / bpm2crotchet(bpm);
-        Ok(beats)
+        Ok(self.tempo_map.seconds2beats(seconds))
     }
     pub fn get_bpm_until<F>(&self, function: F) -> Result<f64, Box<dyn error::Error>>
     where
@@ -307,22 +734,12 @@ impl Level {
                 calling_function: "get_bpm_until",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats,
-                    seconds: ss_seconds,
-                    ..
-                } = *event
-                {
-                    if function(set_speed, ss_beats, ss_seconds) {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                }
+        let mut bpm = self.settings.bpm * self.speed();
+        for segment in self.tempo_map.segments() {
+            if function(segment.set_speed, Some(segment.beats), Some(segment.seconds)) {
+                break;
             }
+            bpm = segment.bpm;
         }
         Ok(bpm)
     }
@@ -332,23 +749,7 @@ impl Level {
                 calling_function: "get_bpm_by_beats",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats,
-                    ..
-                } = *event
-                {
-                    if beats < ss_beats.ok_or(DynamicValueEmptyError)? {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                }
-            }
-        }
-        Ok(bpm)
+        Ok(self.tempo_map.bpm_by_beats(beats))
     }
     pub fn get_bpm_excluding_beats(&self, beats: f64) -> Result<f64, Box<dyn error::Error>> {
         if !self.parsed {
@@ -356,47 +757,15 @@ impl Level {
                 calling_function: "get_bpm_excluding_beats",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats,
-                    ..
-                } = *event
-                {
-                    if beats <= ss_beats.ok_or(DynamicValueEmptyError)? {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                }
-            }
-        }
-        Ok(bpm)
+        Ok(self.tempo_map.bpm_excluding_beats(beats))
     }
     pub fn get_bpm_by_seconds(&self, seconds: f64) -> Result<f64, Box<dyn error::Error>> {
         if !self.parsed {
             return Err(Box::new(LevelIsNotParsedError {
-                calling_function: "get_bpm_by_beats",
+                calling_function: "get_bpm_by_seconds",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    seconds: ss_seconds,
-                    ..
-                } = *event
-                {
-                    if seconds < ss_seconds.ok_or(DynamicValueEmptyError)? {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                }
-            }
-        }
-        Ok(bpm)
+        Ok(self.tempo_map.bpm_by_seconds(seconds))
     }
     pub fn get_bpm_by_floor_seconds(
         &self,
@@ -408,24 +777,9 @@ impl Level {
                 calling_function: "get_bpm_by_floor_seconds",
             }));
         }
-        let mut bpm = self.settings.bpm;
-        'tile_loop: for tile in &self.tiles {
-            for event in &tile.events {
-                if let EventData::Dynamic {
-                    event: DynamicEvents::SetSpeed(set_speed),
-                    seconds: ss_seconds,
-                    ..
-                } = *event
-                {
-                    if floor < set_speed.floor || seconds < ss_seconds.ok_or(DynamicValueEmptyError)? {
-                        break 'tile_loop;
-                    }
-                    bpm = set_speed.get_bpm(bpm);
-                }
-            }
-        }
-        Ok(bpm)
+        Ok(self.tempo_map.bpm_by_floor_seconds(floor, seconds))
     }
+    /// The direction, in degrees, from the planet on `floor` to the planet moving round it.
     pub fn planets_direction(
         &self,
         floor: usize,
@@ -438,32 +792,24 @@ impl Level {
         }
         let bpm = self.get_bpm_by_floor_seconds(floor, seconds)?;
         let spb = bpm2crotchet(bpm);
-        let angle;
-        if floor == 0 {
-            angle = -seconds / spb * 180.0;
+        let angle = if floor == 0 {
+            -seconds / spb * 180.0
         } else {
-            let k = if self.tiles[floor].data.orbit.ok_or(DynamicValueEmptyError)?
-                == Orbit::Clockwise
-            {
-                -1.0
-            } else {
-                1.0
-            };
-            angle = (if self.tiles[floor].angle == 999.0 {
+            let data = &self.tiles[floor].data;
+            let k = orbit_sign(data.orbit.ok_or(DynamicValueEmptyError)?);
+            // With three planets the moving one starts 60 degrees further round.
+            let planets = data.planets.ok_or(DynamicValueEmptyError)?;
+            let start_offset = if planets == Planets::ThreePlanets { 60.0 * k } else { 0.0 };
+            (if self.tiles[floor].angle == 999.0 {
                 self.tiles[floor - 1].angle
             } else {
                 self.tiles[floor].angle - 180.0
-            }) + ((seconds
-                - self.tiles[floor]
-                    .data
-                    .seconds
-                    .ok_or(DynamicValueEmptyError)?)
-                / spb
-                * 180.0
-                * k);
-        }
+            }) + start_offset
+                + ((seconds - data.seconds.ok_or(DynamicValueEmptyError)?) / spb * 180.0 * k)
+        };
         Ok(angle)
     }
+    /// The positions of the red and blue planets.
     pub fn planets_position(
         &self,
         floor: usize,
@@ -474,32 +820,38 @@ impl Level {
                 calling_function: "planets_position",
             }));
         }
-        let pos1;
-        let pos2;
-        pos1 = if self.tiles[floor]
-            .data
-            .stick_to_floors
-            .ok_or(DynamicValueEmptyError)?
-        {
-            self.tiles[floor]
-                .data
-                .position
-                .now
-                .ok_or(DynamicValueEmptyError)?
+        let positions = self.planets_positions(floor, seconds)?;
+        Ok((positions[0], positions[1]))
+    }
+    /// The positions of every planet, red, blue and then green if there are three.
+    ///
+    /// The planets keep to an equilateral triangle while they turn round the one on `floor`.
+    pub fn planets_positions(
+        &self,
+        floor: usize,
+        seconds: f64,
+    ) -> Result<Vec<Vector2D<f64>>, Box<dyn error::Error>> {
+        if !self.parsed {
+            return Err(Box::new(LevelIsNotParsedError {
+                calling_function: "planets_positions",
+            }));
+        }
+        let data = &self.tiles[floor].data;
+        let pivot_position = if data.stick_to_floors.ok_or(DynamicValueEmptyError)? {
+            data.position.now.ok_or(DynamicValueEmptyError)?
         } else {
-            self.tiles[floor]
-                .data
-                .position
-                .orig
-                .ok_or(DynamicValueEmptyError)?
+            data.position.orig.ok_or(DynamicValueEmptyError)?
         };
-        let (sin, cos) = deg2rad(self.planets_direction(floor, seconds)?).sin_cos();
-        pos2 = pos1.clone() + Vector2D::new(cos, sin);
-        if floor % 2 == 0 {
-            Ok((pos1, pos2))
-        } else {
-            Ok((pos2, pos1))
+        let count = data.planets.ok_or(DynamicValueEmptyError)?.count();
+        let pivot = data.pivot.ok_or(DynamicValueEmptyError)?;
+        let direction = self.planets_direction(floor, seconds)?;
+        let k = orbit_sign(data.orbit.ok_or(DynamicValueEmptyError)?);
+        let mut positions = vec![pivot_position; count];
+        for i in 1..count {
+            let (sin, cos) = deg2rad(direction - 60.0 * k * (i - 1) as f64).sin_cos();
+            positions[(pivot + i) % count] = pivot_position + Vector2D::new(cos, sin);
         }
+        Ok(positions)
     }
     pub fn get_floor_by_seconds(&self, seconds: f64) -> Result<usize, Box<dyn error::Error>> {
         if !self.parsed {
@@ -507,17 +859,7 @@ impl Level {
                 calling_function: "get_floor_by_seconds",
             }));
         }
-        let mut floor = self.tiles.len() - 1;
-        for (i, tile) in self.tiles.iter().enumerate() {
-            if seconds < tile.data.seconds().unwrap() {
-                floor = i;
-                break;
-            }
-        }
-        if floor > 0 {
-            floor -= 1;
-        }
-        Ok(floor)
+        Ok(self.tempo_map.floor_by_seconds(seconds))
     }
 
     pub fn get_timing(&self, floor: usize, seconds: f64) -> Result<f64, Box<dyn error::Error>> {
@@ -590,6 +932,38 @@ impl Level {
     }
 }
 
+/// Decodes UTF-8 (with or without a BOM) and UTF-16 (LE or BE, detected by the BOM or by the
+/// zero byte of the leading ASCII character) text.
+fn decode(bytes: &[u8]) -> Result<Cow<'_, str>, LevelLoadError> {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units = bytes
+            .chunks(2)
+            .map(|unit| from_bytes([unit[0], *unit.get(1).unwrap_or(&0)]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map(Cow::Owned)
+            .map_err(LevelLoadError::InvalidUtf16)
+    };
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [first, 0, ..] if *first != 0 => utf16(bytes, u16::from_le_bytes),
+        [0, second, ..] if *second != 0 => utf16(bytes, u16::from_be_bytes),
+        _ => str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(LevelLoadError::InvalidUtf8),
+    }
+}
+
+impl str::FromStr for Level {
+    type Err = LevelLoadError;
+
+    fn from_str(s: &str) -> Result<Level, LevelLoadError> {
+        let json = serde_json_lenient::from_str(s.strip_bom()).map_err(LevelLoadError::Json)?;
+        Level::from_json(&json)
+    }
+}
+
 // #[derive(Getters)]
 // #[getset(get = "pub")]
 // pub struct PlayingLevel {
@@ -14,24 +14,36 @@ impl fmt::Display for LevelParseError {
 }
 impl error::Error for LevelParseError {}
 
+/// Upper bound on the copies a single RepeatEvents may produce per repeated event.
+const MAX_REPETITIONS: u32 = 1 << 16;
+
+/// A problem in the level that [`Level::parse`] skipped instead of failing on.
+#[derive(Debug, Clone)]
+pub struct ParseDiagnostic {
+    pub floor: usize,
+    pub message: String,
+}
+
 impl Level {
     pub fn parse(&mut self) -> Result<(), Box<dyn error::Error>> {
         if self.parsed {
             return Ok(());
         }
-        self.parsed = true;
-        let tiles = &mut self.tiles;
-        let length = tiles.len();
-
-        if length < 2 {
+        if self.tiles.len() < 2 {
             return Err(Box::from(LevelParseError {
-                msg: format!("Tiles are not enough (level.tiles.len() < 2)."),
+                msg: "Tiles are not enough (level.tiles.len() < 2).".to_string(),
             }));
         }
+        self.parsed = true;
+        self.diagnostics.clear();
+        let tiles = &mut self.tiles;
+        let length = tiles.len();
 
         let data = &mut tiles[0].data;
         *data = Default::default();
         data.orbit = Some(Orbit::Clockwise);
+        data.planets = Some(Planets::TwoPlanets);
+        data.pivot = Some(0);
         data.hitsound = Some(self.settings.hitsound);
         data.midspin_hitsound = Some(self.settings.hitsound);
         data.hitsound_volume = Some(self.settings.hitsound_volume);
@@ -56,15 +68,35 @@ impl Level {
         data.color_anim_duration.orig = Some(self.settings.track_color_anim_duration);
         data.color_pulse.orig = Some(self.settings.track_color_pulse);
         data.pulse_length.orig = Some(self.settings.track_pulse_length);
+        data.glow_intensity.orig = Some(self.settings.track_glow_intensity);
         data.style.orig = Some(self.settings.track_style);
+        data.track_animation = Some(self.settings.track_animation);
+        data.beats_ahead = Some(self.settings.beats_ahead);
+        data.track_disappear_animation = Some(self.settings.track_disappear_animation);
+        data.beats_behind = Some(self.settings.beats_behind);
+        data.visible.orig = Some(true);
 
         let mut last_position_offset = (Vector2D::new(0.0, 0.0), Vector2D::new(0.0, 0.0));
+        // The colors before a justThisTile ColorTrack, which the next tile goes back to.
+        let mut colors_before: Option<TileData> = None;
+        // The rotation, scale and opacity before a PositionTrack, which only sets them for
+        // its own tile.
+        let mut transform_before: Option<TileData> = None;
         for i in 0..length {
             let i_is_first = i == 0;
             let i_is_last = i == length - 1;
             if !i_is_first {
-                tiles[i].data = tiles[i - 1].data.clone();
+                tiles[i].data = tiles[i - 1].data;
+                if let Some(colors) = colors_before.take() {
+                    tiles[i].data.copy_colors(&colors);
+                }
+                if let Some(transform) = transform_before.take() {
+                    tiles[i].data.rotation = transform.rotation;
+                    tiles[i].data.scale = transform.scale;
+                    tiles[i].data.opacity = transform.opacity;
+                }
             }
+            let data_before = tiles[i].data;
 
             tiles[i].data.pause_duration = Some(0.0);
             tiles[i].data.hold_duration = Some(0.0);
@@ -78,23 +110,42 @@ impl Level {
                 } = event
                 {
                     static_event.apply(&mut tiles[i].data);
-                    if let StaticEvents::PositionTrack(position_track_event) = static_event {
-                        option_position_track = Some(position_track_event);
+                    match static_event {
+                        StaticEvents::PositionTrack(position_track_event) => {
+                            option_position_track = Some(position_track_event);
+                            transform_before = Some(data_before);
+                        }
+                        StaticEvents::ColorTrack(color_track) if color_track.just_this_tile => {
+                            colors_before = Some(data_before);
+                        }
+                        _ => {}
                     }
                 }
             }
 
             if !i_is_first {
+                // The planet that moves from the last tile lands on this one.
+                let planets = tiles[i - 1].data.planets.unwrap().count();
+                tiles[i].data.pivot = Some((tiles[i - 1].data.pivot.unwrap() + 1) % planets);
                 if tiles[i].angle == 999.0 {
                     tiles[i].data.beats = tiles[i - 1].data.beats;
                 } else {
-                    let mut angle;
-                    let beats;
-                    if tiles[i - 1].angle == 999.0 {
-                        angle = tiles[i - 2].angle - tiles[i].angle;
+                    // Every midspin between this tile and the last regular one flips the direction.
+                    let midspins = tiles[..i]
+                        .iter()
+                        .rev()
+                        .take_while(|tile| tile.angle == 999.0)
+                        .count();
+                    let last_angle = if midspins == i {
+                        0.0
                     } else {
-                        angle = tiles[i - 1].angle - 180.0 - tiles[i].angle;
-                    }
+                        tiles[i - 1 - midspins].angle
+                    };
+                    let mut angle = if midspins % 2 == 1 {
+                        last_angle - tiles[i].angle
+                    } else {
+                        last_angle - 180.0 - tiles[i].angle
+                    };
                     if tiles[i - 1].data.orbit.unwrap() == Orbit::Anticlockwise {
                         angle *= -1.0;
                     }
@@ -102,10 +153,18 @@ impl Level {
                     if angle == 0.0 {
                         angle += 360.0
                     }
+                    if planets == 3 {
+                        // The planet that moves starts 60 degrees further round than the
+                        // last tile, so a straight line takes two thirds of a beat.
+                        angle -= 60.0;
+                        if angle <= 0.0 {
+                            angle += 360.0;
+                        }
+                    }
                     if i == 1 {
                         angle -= 180.0;
                     }
-                    beats = angle / 180.0
+                    let beats = angle / 180.0
                         + tiles[i - 1].data.pause_duration.unwrap()
                         + tiles[i - 1].data.hold_duration.unwrap();
                     *tiles[i].data.beats.as_mut().unwrap() += beats;
@@ -126,16 +185,37 @@ impl Level {
                 }
                 last_position_offset = (Vector2D::new(0.0, 0.0), Vector2D::new(0.0, 0.0));
                 if let Some(position_track) = option_position_track {
-                    *tiles[i].data.editor_position.as_mut().unwrap() +=
-                        position_track.position_offset;
+                    // The offset is from the position of the relativeTo tile, which has to be
+                    // placed already.
+                    let target = position_track.relative_to.calc(i, length - 1);
+                    let (offset, editor_offset) = if target < i {
+                        let position = tiles[i].data.position.orig.unwrap();
+                        let editor_position = tiles[i].data.editor_position.unwrap();
+                        (
+                            tiles[target].data.position.orig.unwrap() - position
+                                + position_track.position_offset,
+                            tiles[target].data.editor_position.unwrap() - editor_position
+                                + position_track.position_offset,
+                        )
+                    } else {
+                        if target > i {
+                            self.diagnostics.push(ParseDiagnostic {
+                                floor: i,
+                                message: format!(
+                                    "PositionTrack is relative to floor {target}, which comes after it"
+                                ),
+                            });
+                        }
+                        (position_track.position_offset, position_track.position_offset)
+                    };
+                    *tiles[i].data.editor_position.as_mut().unwrap() += editor_offset;
                     if !position_track.editor_only {
-                        *tiles[i].data.position.orig.as_mut().unwrap() +=
-                            position_track.position_offset;
+                        *tiles[i].data.position.orig.as_mut().unwrap() += offset;
                     }
                     if position_track.just_this_tile && !i_is_last {
-                        last_position_offset.1 = -position_track.position_offset;
+                        last_position_offset.1 = -editor_offset;
                         if !position_track.editor_only {
-                            last_position_offset.0 = -position_track.position_offset;
+                            last_position_offset.0 = -offset;
                         }
                     }
                     tiles[i].data.stick_to_floors = Some(position_track.stick_to_floors);
@@ -143,67 +223,89 @@ impl Level {
             }
         }
         tiles[0].data.beats = Some(-(self.settings.countdown_ticks as f64));
-        for tile in &mut self.tiles {
-            for event in &mut tile.events {
+
+        for decoration in &mut self.decorations {
+            if let DecorationData::Known { decoration, data } = decoration {
+                *data = decoration.initial_state();
+            }
+        }
+        self.default_text = DefaultText {
+            color: DynamicValue {
+                orig: Some(self.settings.default_text_color),
+                now: None,
+            },
+            shadow_color: DynamicValue {
+                orig: Some(self.settings.default_text_shadow_color),
+                now: None,
+            },
+            congrats_text: DynamicValue {
+                orig: Some(self.settings.congrats_text.clone()),
+                now: None,
+            },
+            perfect_text: DynamicValue {
+                orig: Some(self.settings.perfect_text.clone()),
+                now: None,
+            },
+        };
+        let background = &mut self.background;
+        background.color.orig = Some(self.settings.background_color);
+        background.image.orig = Some(self.settings.bg_image.clone());
+        background.image_color.orig = Some(self.settings.bg_image_color);
+        background.parallax.orig = Some(self.settings.parallax);
+        background.display_mode.orig = Some(self.settings.bg_display_mode);
+        background.image_smoothing.orig = Some(self.settings.image_smoothing);
+        background.lock_rot.orig = Some(self.settings.lock_rot);
+        background.loop_bg.orig = Some(self.settings.loop_bg);
+        background.scaling_ratio.orig = Some(self.settings.scaling_ratio);
+
+        let mut set_speeds = vec![];
+        for (floor, tile) in self.tiles.iter().enumerate() {
+            for event in &tile.events {
                 if let EventData::Dynamic {
                     event: DynamicEvents::SetSpeed(set_speed),
-                    beats: ss_beats,
                     ..
                 } = event
                 {
-                    *ss_beats = Some(tile.data.beats.unwrap() + set_speed.angle_offset / 180.0);
+                    let beats = tile.data.beats.unwrap() + set_speed.angle_offset / 180.0;
+                    set_speeds.push((*set_speed, floor, beats));
                 }
             }
         }
+        set_speeds.sort_by(|(_, _, a_beats), (_, _, b_beats)| a_beats.total_cmp(b_beats));
+        self.tempo_map =
+            TempoMap::new(self.settings.bpm, self.settings.offset / 1000.0, self.speed());
+        for (set_speed, floor, beats) in set_speeds {
+            self.tempo_map.push(set_speed, floor, beats);
+        }
+        for tile in &mut self.tiles {
+            tile.data.seconds = Some(self.tempo_map.beats2seconds(tile.data.beats.unwrap()));
+        }
+        self.tempo_map
+            .set_floor_seconds(self.tiles.iter().map(|tile| tile.data.seconds.unwrap()).collect());
 
         self.dynamic_events.clear();
         let mut repeat_events = vec![];
-        for floor in 0..length {
-            self.tiles[floor].data.seconds = Some(
-                self.beats2seconds(self.tiles[floor].data.beats.unwrap())
-                    .unwrap(),
-            );
-            for event_index in 0..self.tiles[floor].events.len() {
-                let mut beats = 0.0;
-                let mut seconds = 0.0;
-                if let EventData::Dynamic {
-                    event: dynamic_event,
-                    beats: e_beats,
-                    ..
-                } = &self.tiles[floor].events[event_index]
-                {
-                    if let DynamicEvents::SetSpeed(set_speed) = dynamic_event {
-                        beats = e_beats.unwrap() + set_speed.angle_offset / 180.0;
-                        seconds = self.beats2seconds(e_beats.unwrap()).unwrap();
-                    } else {
-                        let bpm = self.get_bpm_until(|ss, _, _| {
-                            floor < ss.floor || dynamic_event.angle_offset() < ss.angle_offset
-                        })?;
-                        let spb = bpm2crotchet(bpm);
-                        seconds = self.tiles[floor].data.seconds.unwrap()
-                            + dynamic_event.angle_offset() / 180.0 * spb;
-                        beats = self.seconds2beats(seconds).unwrap();
-                    }
-                }
+        for tile in &mut self.tiles {
+            let tile_beats = tile.data.beats.unwrap();
+            for event_data in &mut tile.events {
                 if let EventData::Dynamic {
-                    event: dyn_e,
-                    beats: e_beats,
-                    seconds: e_seconds,
-                    ..
-                } = &mut self.tiles[floor].events[event_index]
+                    event,
+                    beats,
+                    seconds,
+                } = event_data
                 {
-                    *e_beats = Some(beats);
-                    *e_seconds = Some(seconds);
+                    let e_beats = tile_beats + event.angle_offset() / 180.0;
+                    *beats = Some(e_beats);
+                    *seconds = Some(self.tempo_map.beats2seconds(e_beats));
 
-                    if let DynamicEvents::RepeatEvents(_) = dyn_e {
-                        repeat_events.push(self.tiles[floor].events[event_index].clone());
+                    if let DynamicEvents::RepeatEvents(_) = event {
+                        repeat_events.push(event_data.clone());
                     }
-                    self.dynamic_events
-                        .push(self.tiles[floor].events[event_index].clone());
+                    self.dynamic_events.push(event_data.clone());
                 }
             }
         }
-        fn match_tag(re_tags: &Vec<String>, e_tags: &Vec<String>) -> bool {
+        fn match_tag(re_tags: &[String], e_tags: &[String]) -> bool {
             for re_tag in re_tags {
                 if e_tags.contains(re_tag) {
                     return true;
@@ -234,10 +336,20 @@ impl Level {
                         {
                             continue;
                         }
-                        let spb = self.get_bpm_by_floor_seconds(event.floor(), *seconds)?;
-                        for i in 1..=(re.repetitions + 1) {
+                        let spb =
+                            bpm2crotchet(self.tempo_map.bpm_by_floor_seconds(event.floor(), *seconds));
+                        if re.repetitions > MAX_REPETITIONS {
+                            self.diagnostics.push(ParseDiagnostic {
+                                floor: re.floor,
+                                message: format!(
+                                    "RepeatEvents repetitions {} clamped to {}",
+                                    re.repetitions, MAX_REPETITIONS
+                                ),
+                            });
+                        }
+                        for i in 1..=(re.repetitions.min(MAX_REPETITIONS) + 1) {
                             let new_seconds = seconds + i as f64 * re.interval * spb;
-                            let new_beats = self.seconds2beats(new_seconds)?;
+                            let new_beats = self.tempo_map.seconds2beats(new_seconds);
                             self.dynamic_events.push(EventData::Dynamic {
                                 event: event.clone(),
                                 beats: Some(new_beats),
@@ -248,7 +360,11 @@ impl Level {
                 }
                 RepeatType::Floor => {
                     let Some(floor_count) = re.floor_count else {
-                        unreachable!()
+                        self.diagnostics.push(ParseDiagnostic {
+                            floor: re.floor,
+                            message: "RepeatEvents of type Floor has no floorCount".to_string(),
+                        });
+                        continue;
                     };
                     for event_data in &self.tiles[re.floor].events {
                         let EventData::Dynamic { event, .. } = event_data else {
@@ -259,16 +375,21 @@ impl Level {
                         {
                             continue;
                         }
-                        let bpm = self.get_bpm_until(|ss, _, _| {
-                            event.floor() < ss.floor || event.angle_offset() < ss.angle_offset
-                        })?;
-                        let spb = bpm2crotchet(bpm);
-                        let offset = event.angle_offset() / 180.0 * spb;
-
-                        for i in 1..=(floor_count + 1) {
+                        let offset_beats = event.angle_offset() / 180.0;
+                        for i in 1..=(floor_count.min(MAX_REPETITIONS) + 1) {
                             let new_floor = event.floor() + i as usize;
-                            let new_seconds = self.tiles[new_floor].data.seconds.unwrap() + offset;
-                            let new_beats = self.seconds2beats(new_seconds)?;
+                            if new_floor >= length {
+                                self.diagnostics.push(ParseDiagnostic {
+                                    floor: re.floor,
+                                    message: format!(
+                                        "RepeatEvents repeats past the last floor ({})",
+                                        length - 1
+                                    ),
+                                });
+                                break;
+                            }
+                            let new_beats = self.tiles[new_floor].data.beats.unwrap() + offset_beats;
+                            let new_seconds = self.tempo_map.beats2seconds(new_beats);
                             let mut new_event = event.clone();
                             if re.execute_on_current_floor {
                                 new_event.set_floor(new_floor)
@@ -300,7 +421,7 @@ impl Level {
             else {
                 unreachable!()
             };
-            a_seconds.partial_cmp(b_seconds).unwrap()
+            a_seconds.total_cmp(b_seconds)
         });
         Ok(())
     }
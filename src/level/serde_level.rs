@@ -1,26 +1,133 @@
 use crate::*;
 use event::*;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use super::{SourceShape, migrate::migrated};
+use serde_json_lenient::Value;
+use std::{error, fmt, io, str};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::SerializeMap};
 
+/// How tile angles are written when saving a level.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AngleFormat {
+    /// `pathData` if every angle has a pathData character, `angleData` otherwise.
+    #[default]
+    Auto,
+    /// The format the level was loaded from, so an unchanged level is saved as it was. Like
+    /// `Auto` for a level built in code or loaded from `pathData`.
+    Source,
+    /// Always `pathData`. Saving fails if some angle has no pathData character.
+    PathData,
+    /// Always `angleData`.
+    AngleData,
+}
+
+/// The text layout of a saved level.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Indented JSON with one value per line.
+    #[default]
+    Pretty,
+    /// The layout the game writes, with a BOM, see [`format_game_style`]. With
+    /// [`AngleFormat::Source`] an unchanged level comes out as the game saved it.
+    Game,
+}
+
+/// Options for [`Level::save_with`] and [`Level::to_string_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct SaveOptions {
+    pub angle_format: AngleFormat,
+    /// Tolerance in degrees when looking up pathData characters, see [`angle2path`].
+    pub angle_epsilon: f64,
+    pub output_format: OutputFormat,
+    /// Write [`LATEST_VERSION`] as `settings.version`, even for a level that was not migrated.
+    /// A migrated level is always saved in the latest version.
+    pub latest_version: bool,
+}
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions {
+            angle_format: AngleFormat::Auto,
+            angle_epsilon: DEFAULT_ANGLE_EPSILON,
+            output_format: OutputFormat::Pretty,
+            latest_version: false,
+        }
+    }
+}
+
+impl SaveOptions {
+    /// The pathData to write for `angle_data`, or `None` to write angleData. With
+    /// [`AngleFormat::Source`], pathData is only written if `source_is_path_data` is set.
+    pub(crate) fn path_data(
+        &self,
+        angle_data: &[f64],
+        source_is_path_data: bool,
+    ) -> Result<Option<String>, String> {
+        match self.angle_format {
+            AngleFormat::AngleData => Ok(None),
+            AngleFormat::Source if !source_is_path_data => Ok(None),
+            AngleFormat::Auto | AngleFormat::Source => {
+                Ok(angles2path_data(angle_data, self.angle_epsilon).ok())
+            }
+            AngleFormat::PathData => angles2path_data(angle_data, self.angle_epsilon)
+                .map(Some)
+                .map_err(|index| {
+                    format!(
+                        "angle {} of tile {} has no pathData character",
+                        angle_data[index],
+                        index + 1
+                    )
+                }),
+        }
+    }
+}
+
+/// Serializes a level with the given options.
+pub(crate) struct LevelWriter<'a> {
+    pub(crate) level: &'a Level,
+    pub(crate) options: &'a SaveOptions,
+}
+impl Serialize for LevelWriter<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        // The first tile is the synthetic one pushed by `Deserialize`, so it has no angle entry.
+        let angle_data: Vec<f64> = self.level.tiles.iter().skip(1).map(|tile| tile.angle).collect();
+        let actions: Vec<&EventData> =
+            self.level.tiles.iter().flat_map(|tile| &tile.events).collect();
+        let path_data = self
+            .options
+            .path_data(&angle_data, self.level.shape.as_ref().is_none_or(|shape| shape.path_data))
+            .map_err(serde::ser::Error::custom)?;
+        match path_data {
+            Some(path_data) => map.serialize_entry("pathData", &path_data)?,
+            None => map.serialize_entry("angleData", &angle_data)?,
+        }
+        if self.options.latest_version {
+            let mut settings =
+                serde_json_lenient::to_value(&self.level.settings).map_err(serde::ser::Error::custom)?;
+            settings["version"] = Value::from(LATEST_VERSION);
+            map.serialize_entry("settings", &settings)?;
+        } else {
+            map.serialize_entry("settings", &self.level.settings)?;
+        }
+        map.serialize_entry("actions", &actions)?;
+        if !self.level.decorations.is_empty() {
+            map.serialize_entry("decorations", &self.level.decorations)?;
+        }
+        map.end()
+    }
+}
 impl Serialize for Level {
-    fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        todo!()
-        // let mut map = serializer.serialize_map(Some(3))?;
-        // let mut angle_data = vec![];
-        // let mut actions = vec![];
-        // for tile in &self.tiles {
-        //     angle_data.push(tile.angle);
-        //     for event in &tile.events {
-        //         actions.push(event.clone());
-        //     }
-        // }
-        // map.serialize_entry("angleData", &angle_data);
-        // map.serialize_entry("settings", &self.settings);
-        // map.serialize_entry("actions", &actions);
-        // map.end()
+        LevelWriter {
+            level: self,
+            options: &SaveOptions::default(),
+        }
+        .serialize(serializer)
     }
 }
 impl<'de> Deserialize<'de> for Level {
@@ -28,40 +135,87 @@ impl<'de> Deserialize<'de> for Level {
     where
         D: Deserializer<'de>,
     {
-        use serde::de::Error as e;
-        let json: serde_json_lenient::Value = serde::Deserialize::deserialize(deserializer)?;
-        let object = json
-            .as_object()
-            .ok_or(e::custom("The value is not an object"))?;
-        let settings_result = serde_json_lenient::from_value(object["settings"].clone());
-        if let Err(err) = settings_result {
-            return Err(e::custom(&err.to_string()));
-        }
-        let settings: Settings = settings_result.unwrap();
+        let json: Value = serde::Deserialize::deserialize(deserializer)?;
+        Level::from_json(&json).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Level {
+    /// Builds a level from its JSON, migrating older versions first, see [`migrate`].
+    pub fn from_json(json: &Value) -> Result<Level, LevelLoadError> {
+        let source = json;
+        let (json, migrations) = migrated(json);
+        let object = json.as_object().ok_or(LevelLoadError::NotAnObject)?;
+        let settings_json = object.get("settings").ok_or(LevelLoadError::MissingSettings)?;
+        let settings: Settings = serde_path_to_error::deserialize(settings_json).map_err(|err| {
+            let field = match err.path().to_string() {
+                path if path == "." => missing_field(err.inner()).unwrap_or_default(),
+                path => path,
+            };
+            LevelLoadError::InvalidSettings {
+                field,
+                source: err.into_inner(),
+            }
+        })?;
         let mut tiles: Vec<Tile> = vec![];
         tiles.push(Tile::new(0.0));
-        if object.contains_key("angleData") {
-            for angle in object["angleData"].as_array().ok_or(e::custom(""))? {
-                tiles.push(Tile::new(angle.as_f64().ok_or(e::custom(""))?));
-            }
-        } else if object.contains_key("pathData") {
-            for path in object["pathData"].as_str().ok_or(e::custom(""))?.chars() {
-                match path2angle(path) {
-                    Ok(angle) => {
-                        tiles.push(Tile::new(angle));
-                    }
-                    Err(_) => {
-                        return Err(e::custom("msg"));
-                    }
-                }
+        if let Some(angle_data) = object.get("angleData") {
+            let angle_data = angle_data
+                .as_array()
+                .ok_or(LevelLoadError::InvalidAngleData { index: None })?;
+            for (index, angle) in angle_data.iter().enumerate() {
+                let angle = angle
+                    .as_f64()
+                    .ok_or(LevelLoadError::InvalidAngleData { index: Some(index) })?;
+                tiles.push(Tile::new(angle));
             }
+        } else if let Some(path_data) = object.get("pathData") {
+            let path_data = path_data.as_str().ok_or(LevelLoadError::InvalidPathData)?;
+            let angles = path_data2angles(path_data)
+                .map_err(|(index, path)| LevelLoadError::InvalidPathChar { index, path })?;
+            tiles.extend(angles.into_iter().map(Tile::new));
         } else {
-            return Err(serde::de::Error::custom("msg"));
+            return Err(LevelLoadError::MissingTiles);
         }
-        for data in object["actions"].as_array().ok_or(e::custom(""))? {
-            let object = data.as_object().ok_or(e::custom(""))?;
-            let floor = object["floor"].as_u64().ok_or(e::custom(""))? as usize;
-            let result: Result<Events, _> = serde_json_lenient::from_value(data.clone());
+        let actions = match object.get("actions") {
+            Some(actions) => actions.as_array().ok_or(LevelLoadError::InvalidActions)?.as_slice(),
+            None => &[],
+        };
+        for (index, data) in actions.iter().enumerate() {
+            let invalid_action = |floor, event_type, message: &str| LevelLoadError::InvalidAction {
+                index,
+                floor,
+                event_type,
+                message: message.to_string(),
+            };
+            let object = data
+                .as_object()
+                .ok_or_else(|| invalid_action(None, None, "action is not an object"))?;
+            let event_type = object
+                .get("eventType")
+                .and_then(|event_type| event_type.as_str())
+                .map(|event_type| event_type.to_string());
+            let floor = object
+                .get("floor")
+                .and_then(|floor| floor.as_u64())
+                .ok_or_else(|| {
+                    invalid_action(
+                        None,
+                        event_type.clone(),
+                        "`floor` is missing or not a non-negative integer",
+                    )
+                })? as usize;
+            let event_type = event_type.ok_or_else(|| {
+                invalid_action(Some(floor), None, "`eventType` is missing or not a string")
+            })?;
+            if floor >= tiles.len() {
+                return Err(LevelLoadError::FloorOutOfRange {
+                    index,
+                    floor,
+                    tile_count: tiles.len(),
+                });
+            }
+            let result: Result<Events, _> = Events::deserialize(data);
             match result {
                 Ok(event) => {
                     tiles[floor].events.push(match event {
@@ -73,13 +227,173 @@ impl<'de> Deserialize<'de> for Level {
                         },
                     });
                 }
-                Err(_err) => {
-                    // if !err.to_string().starts_with("unknown variant") {
-                    //     println!("{}", err);
-                    // }
+                Err(_) => {
+                    tiles[floor].events.push(EventData::Unknown {
+                        event_type,
+                        floor,
+                        raw_json: object.clone(),
+                    });
+                }
+            }
+        }
+        let decorations = match object.get("decorations") {
+            Some(decorations) => decorations
+                .as_array()
+                .ok_or(LevelLoadError::InvalidDecorations)?
+                .as_slice(),
+            None => &[],
+        };
+        let mut level = Level::new(tiles, settings);
+        for (index, data) in decorations.iter().enumerate() {
+            let invalid_decoration = |message: &str| LevelLoadError::InvalidDecoration {
+                index,
+                message: message.to_string(),
+            };
+            let object = data
+                .as_object()
+                .ok_or_else(|| invalid_decoration("decoration is not an object"))?;
+            let event_type = object
+                .get("eventType")
+                .and_then(|event_type| event_type.as_str())
+                .ok_or_else(|| invalid_decoration("`eventType` is missing or not a string"))?;
+            level.decorations.push(match Decorations::deserialize(data) {
+                Ok(decoration) => DecorationData::Known {
+                    decoration,
+                    data: Default::default(),
+                },
+                Err(_) => DecorationData::Unknown {
+                    event_type: event_type.to_string(),
+                    raw_json: object.clone(),
+                },
+            });
+        }
+        level.migrations = migrations;
+        level.shape = Some(SourceShape::new(source, &json, &level)?);
+        Ok(level)
+    }
+}
+
+fn missing_field(err: &serde_json_lenient::Error) -> Option<String> {
+    let message = err.to_string();
+    let rest = message.strip_prefix("missing field `")?;
+    Some(rest[..rest.find('`')?].to_string())
+}
+
+/// Error returned when a level file cannot be loaded.
+#[derive(Debug)]
+pub enum LevelLoadError {
+    Io(io::Error),
+    InvalidUtf8(str::Utf8Error),
+    InvalidUtf16(std::char::DecodeUtf16Error),
+    /// The text is not valid JSON.
+    Json(serde_json_lenient::Error),
+    NotAnObject,
+    /// Neither `angleData` nor `pathData` is present.
+    MissingTiles,
+    /// `angleData` is not an array (`index` is `None`) or one of its items is not a number.
+    InvalidAngleData { index: Option<usize> },
+    InvalidPathData,
+    InvalidPathChar { index: usize, path: char },
+    MissingSettings,
+    /// `field` is the path of the offending key inside `settings`.
+    InvalidSettings {
+        field: String,
+        source: serde_json_lenient::Error,
+    },
+    InvalidActions,
+    /// `actions[index]` is malformed.
+    InvalidAction {
+        index: usize,
+        floor: Option<usize>,
+        event_type: Option<String>,
+        message: String,
+    },
+    /// `actions[index]` refers to a floor that does not exist.
+    FloorOutOfRange {
+        index: usize,
+        floor: usize,
+        tile_count: usize,
+    },
+    InvalidDecorations,
+    /// `decorations[index]` is malformed.
+    InvalidDecoration { index: usize, message: String },
+    /// The lossless loader could not follow the document at byte `offset`.
+    UnsupportedSyntax { offset: usize },
+}
+impl LevelLoadError {
+    /// Where in the document the error occurred, e.g. `actions[12]` or `settings.trackColor`.
+    pub fn location(&self) -> String {
+        match self {
+            Self::Io(_) | Self::InvalidUtf8(_) | Self::InvalidUtf16(_) => "(input)".to_string(),
+            Self::Json(err) => format!("line {}, column {}", err.line(), err.column()),
+            Self::NotAnObject | Self::MissingTiles | Self::MissingSettings => "(root)".to_string(),
+            Self::InvalidAngleData { index: None } => "angleData".to_string(),
+            Self::InvalidAngleData { index: Some(index) } => format!("angleData[{}]", index),
+            Self::InvalidPathData => "pathData".to_string(),
+            Self::InvalidPathChar { index, .. } => format!("pathData[{}]", index),
+            Self::InvalidSettings { field, .. } => format!("settings.{}", field),
+            Self::InvalidActions => "actions".to_string(),
+            Self::InvalidAction { index, .. } | Self::FloorOutOfRange { index, .. } => {
+                format!("actions[{}]", index)
+            }
+            Self::InvalidDecorations => "decorations".to_string(),
+            Self::InvalidDecoration { index, .. } => format!("decorations[{}]", index),
+            Self::UnsupportedSyntax { offset } => format!("byte {}", offset),
+        }
+    }
+}
+impl fmt::Display for LevelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.location())?;
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::InvalidUtf8(err) => write!(f, "{}", err),
+            Self::InvalidUtf16(err) => write!(f, "{}", err),
+            Self::Json(err) => write!(f, "{}", err),
+            Self::NotAnObject => write!(f, "the level is not a JSON object"),
+            Self::MissingTiles => write!(f, "neither `angleData` nor `pathData` is present"),
+            Self::InvalidAngleData { index: None } => write!(f, "expected an array of numbers"),
+            Self::InvalidAngleData { index: Some(_) } => write!(f, "expected a number"),
+            Self::InvalidPathData => write!(f, "expected a string"),
+            Self::InvalidPathChar { path, .. } => write!(f, "unknown path character {:?}", path),
+            Self::MissingSettings => write!(f, "`settings` is missing"),
+            Self::InvalidSettings { source, .. } => write!(f, "{}", source),
+            Self::InvalidActions => write!(f, "expected an array of actions"),
+            Self::InvalidAction {
+                floor,
+                event_type,
+                message,
+                ..
+            } => {
+                if let Some(floor) = floor {
+                    write!(f, "(floor {}) ", floor)?;
+                }
+                if let Some(event_type) = event_type {
+                    write!(f, "({}) ", event_type)?;
                 }
+                write!(f, "{}", message)
             }
+            Self::FloorOutOfRange {
+                floor, tile_count, ..
+            } => write!(
+                f,
+                "floor {} is out of range (the level has {} tiles)",
+                floor, tile_count
+            ),
+            Self::InvalidDecorations => write!(f, "expected an array of decorations"),
+            Self::InvalidDecoration { message, .. } => write!(f, "{}", message),
+            Self::UnsupportedSyntax { .. } => write!(f, "unsupported syntax for lossless loading"),
+        }
+    }
+}
+impl error::Error for LevelLoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::InvalidUtf8(err) => Some(err),
+            Self::InvalidUtf16(err) => Some(err),
+            Self::Json(err) | Self::InvalidSettings { source: err, .. } => Some(err),
+            _ => None,
         }
-        Ok(Level::new(tiles, settings))
     }
 }
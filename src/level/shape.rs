@@ -0,0 +1,164 @@
+use super::{LevelWriter, lossless::align};
+use crate::*;
+use serde_json_lenient::{Map, Value};
+
+/// The keys a loaded level had and their order, used to save it without reordering keys or
+/// writing out defaults the source left out.
+#[derive(Debug, Clone)]
+pub(crate) struct SourceShape {
+    /// Whether the tiles were given as `pathData`, which [`AngleFormat::Source`] keeps.
+    pub(crate) path_data: bool,
+    /// The level as the writer serialized it right after loading. A key the source lacks is
+    /// only written once its value differs from this.
+    baseline: Value,
+    root_keys: Vec<String>,
+    /// The keys of the settings and of every source action and decoration, after migrating.
+    settings_keys: Vec<String>,
+    action_keys: Vec<Vec<String>>,
+    decoration_keys: Vec<Vec<String>>,
+    /// The index in the source `actions` of every event, in tile order.
+    action_order: Vec<usize>,
+}
+
+impl SourceShape {
+    pub(crate) fn new(
+        source: &Value,
+        migrated: &Value,
+        level: &Level,
+    ) -> Result<Self, LevelLoadError> {
+        let baseline = serde_json_lenient::to_value(LevelWriter {
+            level,
+            options: &SaveOptions {
+                angle_format: AngleFormat::AngleData,
+                ..Default::default()
+            },
+        })
+        .map_err(LevelLoadError::Json)?;
+        let items_keys = |json: &Value, key: &str| -> Vec<Vec<String>> {
+            match json.get(key).and_then(|items| items.as_array()) {
+                Some(items) => items.iter().map(keys).collect(),
+                None => vec![],
+            }
+        };
+        let floors: Vec<u64> = match source.get("actions").and_then(|actions| actions.as_array()) {
+            Some(actions) => actions
+                .iter()
+                .map(|action| action["floor"].as_u64().unwrap_or_default())
+                .collect(),
+            None => vec![],
+        };
+        let mut action_order: Vec<usize> = (0..floors.len()).collect();
+        action_order.sort_by_key(|&index| floors[index]);
+        Ok(SourceShape {
+            path_data: source.get("angleData").is_none() && source.get("pathData").is_some(),
+            baseline,
+            root_keys: keys(source),
+            settings_keys: migrated.get("settings").map(keys).unwrap_or_default(),
+            action_keys: items_keys(migrated, "actions"),
+            decoration_keys: items_keys(migrated, "decorations"),
+            action_order,
+        })
+    }
+
+    /// Lays `current`, the writer's output for the level, out like the source.
+    pub(crate) fn apply(&self, current: &Value) -> Value {
+        let Some(current) = current.as_object() else {
+            return current.clone();
+        };
+        let mut shaped = current.clone();
+        if let Some(Value::Object(settings)) = current.get("settings") {
+            let shape = shape_object(settings, self.baseline.get("settings"), &self.settings_keys);
+            shaped.insert("settings".to_string(), Value::Object(shape));
+        }
+        let decoration_order: Vec<usize> = (0..self.decoration_keys.len()).collect();
+        for (key, keys, order) in [
+            ("actions", &self.action_keys, &self.action_order),
+            ("decorations", &self.decoration_keys, &decoration_order),
+        ] {
+            if let (Some(Value::Array(items)), Some(Value::Array(baseline))) =
+                (current.get(key), self.baseline.get(key))
+            {
+                let items = shape_array(items, baseline, keys, order);
+                shaped.insert(key.to_string(), Value::Array(items));
+            }
+        }
+        // The angles keep the position of whichever angle key the source had.
+        let root_keys: Vec<String> = self
+            .root_keys
+            .iter()
+            .map(|key| match key.as_str() {
+                "angleData" | "pathData" if current.contains_key("pathData") => "pathData".into(),
+                "angleData" | "pathData" => "angleData".into(),
+                _ => key.clone(),
+            })
+            .collect();
+        Value::Object(shape_object(&shaped, Some(&self.baseline), &root_keys))
+    }
+}
+
+fn keys(json: &Value) -> Vec<String> {
+    json.as_object()
+        .map(|object| object.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// `object` with the source `keys` first, in their order, followed by the keys the source
+/// lacks whose value differs from `baseline`.
+fn shape_object(
+    object: &Map<String, Value>,
+    baseline: Option<&Value>,
+    keys: &[String],
+) -> Map<String, Value> {
+    let mut shaped: Map<String, Value> = keys
+        .iter()
+        .filter_map(|key| Some((key.clone(), object.get(key)?.clone())))
+        .collect();
+    for (key, value) in object {
+        if !shaped.contains_key(key) && baseline.and_then(|baseline| baseline.get(key)) != Some(value)
+        {
+            shaped.insert(key.clone(), value.clone());
+        }
+    }
+    shaped
+}
+
+/// Shapes every item of `items` after the baseline item it was aligned with, or paired with
+/// by position between two aligned items. `order` maps baseline indices to source indices.
+fn shape_array(
+    items: &[Value],
+    baseline: &[Value],
+    keys: &[Vec<String>],
+    order: &[usize],
+) -> Vec<Value> {
+    let mut pairs: Vec<Option<usize>> = vec![None; items.len()];
+    let mut next = (0, 0);
+    for (item, base) in align(items, baseline)
+        .into_iter()
+        .chain([(items.len(), baseline.len())])
+    {
+        for (offset, pair) in pairs[next.0..item].iter_mut().enumerate() {
+            if next.1 + offset < base {
+                *pair = Some(next.1 + offset);
+            }
+        }
+        if let Some(pair) = pairs.get_mut(item) {
+            *pair = Some(base);
+        }
+        next = (item + 1, base + 1);
+    }
+    items
+        .iter()
+        .zip(pairs)
+        .map(|(item, pair)| {
+            let source_keys = pair.and_then(|base| keys.get(*order.get(base)?));
+            match (item, source_keys) {
+                (Value::Object(object), Some(source_keys)) => Value::Object(shape_object(
+                    object,
+                    pair.map(|base| &baseline[base]),
+                    source_keys,
+                )),
+                _ => item.clone(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,135 @@
+use crate::*;
+use event::*;
+
+/// The point where a SetSpeed takes effect.
+#[derive(Debug, Clone, Copy)]
+pub struct TempoSegment {
+    pub set_speed: SetSpeed,
+    pub floor: usize,
+    pub beats: f64,
+    pub seconds: f64,
+    /// The BPM from this point on.
+    pub bpm: f64,
+}
+
+/// The SetSpeed changes of a level sorted by time, with the cumulative beats and seconds of
+/// every change, so that conversions between beats and seconds are binary searches.
+/// Built by [`Level::parse`].
+///
+/// Seconds and BPMs are in playback time, i.e. already scaled by the playback speed.
+#[derive(Debug, Default, Clone)]
+pub struct TempoMap {
+    bpm: f64,
+    offset: f64,
+    speed: f64,
+    segments: Vec<TempoSegment>,
+    /// The indices of `segments` sorted by floor, and whether that is their own order.
+    floor_order: Vec<usize>,
+    floor_sorted: bool,
+    floor_seconds: Vec<f64>,
+}
+
+impl TempoMap {
+    /// `bpm` is the BPM before the first SetSpeed and `offset` the song seconds at beat 0.
+    /// `speed` is the playback speed of the song (1.0 at 100% pitch).
+    pub fn new(bpm: f64, offset: f64, speed: f64) -> Self {
+        TempoMap {
+            bpm: bpm * speed,
+            offset: offset / speed,
+            speed,
+            floor_sorted: true,
+            ..Default::default()
+        }
+    }
+    /// Adds a SetSpeed at `beats`. SetSpeeds must be pushed in order of their beats, which is
+    /// not always floor order.
+    pub fn push(&mut self, set_speed: SetSpeed, floor: usize, beats: f64) {
+        let (last_beats, last_seconds, last_bpm) = match self.segments.last() {
+            Some(last) => (last.beats, last.seconds, last.bpm),
+            None => (0.0, self.offset, self.bpm),
+        };
+        if self.segments.last().is_some_and(|last| last.floor > floor) {
+            self.floor_sorted = false;
+        }
+        let index = self
+            .floor_order
+            .partition_point(|&index| self.segments[index].floor <= floor);
+        self.floor_order.insert(index, self.segments.len());
+        self.segments.push(TempoSegment {
+            set_speed,
+            floor,
+            beats,
+            seconds: last_seconds + bpm2crotchet(last_bpm) * (beats - last_beats),
+            bpm: self.next_bpm(set_speed, last_bpm),
+        });
+    }
+    fn next_bpm(&self, set_speed: SetSpeed, last_bpm: f64) -> f64 {
+        match set_speed.speed_type {
+            SpeedType::Bpm => set_speed.beats_per_minute * self.speed,
+            SpeedType::Multiplier => set_speed.get_bpm(last_bpm),
+        }
+    }
+    /// Records the seconds of every floor, in floor order.
+    pub fn set_floor_seconds(&mut self, floor_seconds: Vec<f64>) {
+        self.floor_seconds = floor_seconds;
+    }
+    pub fn segments(&self) -> &[TempoSegment] {
+        &self.segments
+    }
+
+    /// The segment in effect after the first `count` SetSpeeds.
+    fn segment(&self, count: usize) -> (f64, f64, f64) {
+        match count.checked_sub(1) {
+            Some(index) => {
+                let segment = &self.segments[index];
+                (segment.beats, segment.seconds, segment.bpm)
+            }
+            None => (0.0, self.offset, self.bpm),
+        }
+    }
+    fn bpm_after(&self, count: usize) -> f64 {
+        self.segment(count).2
+    }
+
+    pub fn beats2seconds(&self, beats: f64) -> f64 {
+        let count = self.segments.partition_point(|segment| segment.beats < beats);
+        let (segment_beats, segment_seconds, bpm) = self.segment(count);
+        segment_seconds + bpm2crotchet(bpm) * (beats - segment_beats)
+    }
+    pub fn seconds2beats(&self, seconds: f64) -> f64 {
+        let count = self.segments.partition_point(|segment| segment.seconds < seconds);
+        let (segment_beats, segment_seconds, bpm) = self.segment(count);
+        segment_beats + (seconds - segment_seconds) / bpm2crotchet(bpm)
+    }
+    /// The BPM at `beats`, including a SetSpeed exactly at `beats`.
+    pub fn bpm_by_beats(&self, beats: f64) -> f64 {
+        self.bpm_after(self.segments.partition_point(|segment| segment.beats <= beats))
+    }
+    /// The BPM at `beats`, excluding a SetSpeed exactly at `beats`.
+    pub fn bpm_excluding_beats(&self, beats: f64) -> f64 {
+        self.bpm_after(self.segments.partition_point(|segment| segment.beats < beats))
+    }
+    pub fn bpm_by_seconds(&self, seconds: f64) -> f64 {
+        self.bpm_after(self.segments.partition_point(|segment| segment.seconds <= seconds))
+    }
+    /// The BPM at `seconds`, ignoring SetSpeeds on floors after `floor`. SetSpeeds are taken
+    /// in floor order up to the first one after `seconds`, so a `Multiplier` multiplies the
+    /// BPM of the SetSpeeds on the floors before it.
+    pub fn bpm_by_floor_seconds(&self, floor: usize, seconds: f64) -> f64 {
+        let in_effect =
+            |segment: &TempoSegment| segment.floor <= floor && segment.seconds <= seconds;
+        if self.floor_sorted {
+            // Floors and seconds both only grow, so the SetSpeeds in effect are a prefix.
+            return self.bpm_after(self.segments.partition_point(in_effect));
+        }
+        let segments = self.floor_order.iter().map(|&index| &self.segments[index]);
+        segments
+            .take_while(|segment| in_effect(segment))
+            .fold(self.bpm, |bpm, segment| self.next_bpm(segment.set_speed, bpm))
+    }
+    /// The floor that is being played at `seconds`.
+    pub fn floor_by_seconds(&self, seconds: f64) -> usize {
+        let count = self.floor_seconds.partition_point(|&floor_seconds| floor_seconds <= seconds);
+        count.clamp(1, self.floor_seconds.len().max(1)) - 1
+    }
+}
@@ -13,6 +13,14 @@ impl Level {
         for tile in &mut self.tiles {
             tile.data.orig2now();
         }
+        for decoration in &mut self.decorations {
+            if let DecorationData::Known { data, .. } = decoration {
+                data.orig2now();
+            }
+        }
+        self.default_text.orig2now();
+        self.background.orig2now();
+        self.screen_effects = ScreenEffects::default();
         for i in 0..self.dynamic_events.len() {
             let EventData::Dynamic {
                 event,
@@ -31,6 +39,7 @@ impl Level {
                 )
                 .unwrap();
         }
+        self.animate_track(seconds);
         Ok(())
     }
     fn calc_camera_player(
@@ -1,11 +1,11 @@
 //! Rusty-adofai is an open-source adofai parser built in Rust.
 //! ## Example
-//! ```rust
+//! ```rust,no_run
 //! use rusty_adofai as adofai;
 //! 
 //! fn main() {
 //!     const FILE_PATH: &str = "F:\\Levels\\(No VFX) Hello (BPM) 2025\\Hello (BPM) 2025.adofai";
-//!     let level = adofai::Level::open(FILE_PATH).unwrap();
+//!     let mut level = adofai::Level::open(FILE_PATH).unwrap();
 //!     level.parse().unwrap();
 //!     for tile in &level.tiles {
 //!         println!("{}", tile.data.seconds().unwrap());
@@ -18,6 +18,10 @@ pub use rgb;
 
 mod tile;
 pub use tile::*;
+mod decoration;
+pub use decoration::*;
+mod effects;
+pub use effects::*;
 mod level;
 pub use level::*;
 pub mod event;
@@ -42,3 +46,14 @@ pub const fn bpm2mspb(bpm: f64) -> f64 {
     // 60.0 / bpm * 1000.0
     60000.0 / bpm
 }
+
+/// Interpolates every channel of a color, rounding to the nearest value.
+pub(crate) fn lerp_rgba(from: rgb::Rgba<u8>, to: rgb::Rgba<u8>, t: f64) -> rgb::Rgba<u8> {
+    let lerp = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+    rgb::Rgba {
+        r: lerp(from.r, to.r),
+        g: lerp(from.g, to.g),
+        b: lerp(from.b, to.b),
+        a: lerp(from.a, to.a),
+    }
+}
@@ -8,9 +8,9 @@ where
     S: Serializer,
 {
     let string = if rgba.a == 255 {
-        format!("{:X}{:X}{:X}", rgba.r, rgba.g, rgba.b)
+        format!("{:02x}{:02x}{:02x}", rgba.r, rgba.g, rgba.b)
     } else {
-        format!("{:X}{:X}{:X}{:X}", rgba.r, rgba.g, rgba.b, rgba.a)
+        format!("{:02x}{:02x}{:02x}{:02x}", rgba.r, rgba.g, rgba.b, rgba.a)
     };
     s.serialize_str(&string)
 }
@@ -19,14 +19,15 @@ where
     D: Deserializer<'de>,
 {
     let res: serde_json_lenient::Value = Deserialize::deserialize(de)?;
-    let src = res.as_str().ok_or(serde::de::Error::custom(""))?;
-    let color = u32::from_str_radix(src, 16)
-        .ok()
-        .ok_or(serde::de::Error::custom(""))?;
-    let color = if src.len() == 6 {
-        (color * 0x100) + 0xff
-    } else {
-        color
+    let src = res
+        .as_str()
+        .ok_or_else(|| serde::de::Error::custom("expected a hex color string"))?;
+    let invalid_color = || serde::de::Error::custom(format!("invalid hex color {:?}", src));
+    let color = u32::from_str_radix(src, 16).map_err(|_| invalid_color())?;
+    let color = match src.len() {
+        6 => (color * 0x100) + 0xff,
+        8 => color,
+        _ => return Err(invalid_color()),
     };
     Ok(Rgba {
         r: (color / (0x100 * 0x100 * 0x100)) as u8,
@@ -35,6 +36,24 @@ where
         a: (color % 0x100) as u8,
     })
 }
+pub(crate) fn ser_rgba_option_u8<S>(rgba: &Option<Rgba<u8>>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match rgba {
+        Some(rgba) => ser_rgba_u8(rgba, s),
+        None => s.serialize_none(),
+    }
+}
+pub(crate) fn de_rgba_option_u8<'de, D>(de: D) -> Result<Option<Rgba<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(de)? {
+        Value::Null => Ok(None),
+        value => de_rgba_u8(value).map(Some).map_err(serde::de::Error::custom),
+    }
+}
 pub(crate) fn de_bool<'de, D>(de: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
@@ -44,7 +63,9 @@ where
         Value::Bool(b) => Ok(b),
         Value::String(ref s) if s == "Enabled" => Ok(true),
         Value::String(ref s) if s == "Disabled" => Ok(false),
-        _ => Err(serde::de::Error::custom("Unexpected value")),
+        _ => Err(serde::de::Error::custom(
+            "expected a boolean, \"Enabled\" or \"Disabled\"",
+        )),
     }
 }
 pub(crate) fn ser_vector2d_f64<S>(v: &Vector2D<f64>, s: S) -> Result<S::Ok, S::Error>
@@ -58,9 +79,12 @@ where
     D: Deserializer<'de>,
 {
     let result: serde_json_lenient::Value = Deserialize::deserialize(de)?;
-    let array = result.as_array().ok_or(serde::de::Error::custom(""))?;
-    let x = array[0].as_f64().ok_or(serde::de::Error::custom(""))?;
-    let y = array[1].as_f64().ok_or(serde::de::Error::custom(""))?;
+    let expected = || serde::de::Error::custom("expected an array of two numbers");
+    let [x, y] = result.as_array().ok_or_else(expected)?.as_slice() else {
+        return Err(expected());
+    };
+    let x = x.as_f64().ok_or_else(expected)?;
+    let y = y.as_f64().ok_or_else(expected)?;
     Ok(Vector2D { x, y })
 }
 pub(crate) fn ser_vector2d_option_f64<S>(v: &Vector2D<Option<f64>>, s: S) -> Result<S::Ok, S::Error>
@@ -74,18 +98,19 @@ where
     D: Deserializer<'de>,
 {
     let result: serde_json_lenient::Value = Deserialize::deserialize(de)?;
-    let array = result.as_array().ok_or(serde::de::Error::custom(""))?;
-    let x = match &array[0] {
-        Value::Number(number) => Ok(Some(number.as_f64().unwrap())),
-        Value::Null => Ok(None),
-        _ => Err(serde::de::Error::custom("")),
-    }?;
-    let y = match &array[1] {
-        Value::Number(number) => Ok(Some(number.as_f64().unwrap())),
+    let expected = || serde::de::Error::custom("expected an array of two numbers or nulls");
+    let [x, y] = result.as_array().ok_or_else(expected)?.as_slice() else {
+        return Err(expected());
+    };
+    let component = |value: &Value| match value {
+        Value::Number(number) => Ok(number.as_f64()),
         Value::Null => Ok(None),
-        _ => Err(serde::de::Error::custom("")),
-    }?;
-    Ok(Vector2D { x, y })
+        _ => Err(expected()),
+    };
+    Ok(Vector2D {
+        x: component(x)?,
+        y: component(y)?,
+    })
 }
 pub(crate) fn ser_event_tag<S>(v: &Vec<String>, s: S) -> Result<S::Ok, S::Error>
 where
@@ -98,6 +123,66 @@ where
     D: Deserializer<'de>,
 {
     let result: serde_json_lenient::Value = Deserialize::deserialize(de)?;
-    let s = result.as_str().ok_or(serde::de::Error::custom(""))?;
+    let s = result
+        .as_str()
+        .ok_or_else(|| serde::de::Error::custom("expected a string of space-separated tags"))?;
     Ok(s.split_whitespace().map(|s| s.to_string()).collect())
 }
+
+pub(crate) const fn f64_1() -> f64 {
+    1.0
+}
+pub(crate) const fn f64_10() -> f64 {
+    10.0
+}
+pub(crate) const fn u32_1() -> u32 {
+    1
+}
+pub(crate) const fn u32_4() -> u32 {
+    4
+}
+pub(crate) const fn bool_true() -> bool {
+    true
+}
+pub(crate) const fn vector2d_1() -> Vector2D<f64> {
+    Vector2D { x: 1.0, y: 1.0 }
+}
+pub(crate) const fn vector2d_100() -> Vector2D<f64> {
+    Vector2D { x: 100.0, y: 100.0 }
+}
+pub(crate) const fn rgba_000000() -> Rgba<u8> {
+    Rgba { r: 0x00, g: 0x00, b: 0x00, a: 0xff }
+}
+pub(crate) const fn rgba_ffffff() -> Rgba<u8> {
+    Rgba { r: 0xff, g: 0xff, b: 0xff, a: 0xff }
+}
+pub(crate) const fn rgba_003f52() -> Rgba<u8> {
+    Rgba { r: 0x00, g: 0x3f, b: 0x52, a: 0xff }
+}
+pub(crate) const fn rgba_101121() -> Rgba<u8> {
+    Rgba { r: 0x10, g: 0x11, b: 0x21, a: 0xff }
+}
+pub(crate) const fn rgba_00000050() -> Rgba<u8> {
+    Rgba { r: 0x00, g: 0x00, b: 0x00, a: 0x50 }
+}
+pub(crate) const fn f64_50() -> f64 {
+    50.0
+}
+pub(crate) const fn f64_100() -> f64 {
+    100.0
+}
+pub(crate) const fn f64_180() -> f64 {
+    180.0
+}
+pub(crate) const fn f64_minus_1() -> f64 {
+    -1.0
+}
+pub(crate) const fn rgba_ff0000() -> Rgba<u8> {
+    Rgba { r: 0xff, g: 0x00, b: 0x00, a: 0xff }
+}
+pub(crate) const fn rgba_debb7b() -> Rgba<u8> {
+    Rgba { r: 0xde, g: 0xbb, b: 0x7b, a: 0xff }
+}
+pub(crate) fn font_default() -> String {
+    "Default".to_string()
+}
@@ -35,6 +35,25 @@ impl<T: Clone> DynamicValue<T> {
     pub(crate) fn orig2now(&mut self) {
         self.now = self.orig.clone();
     }
+    /// The value now, or the original value if it has not been set yet.
+    pub fn current(&self) -> Result<T, DynamicValueEmptyError> {
+        self.now.clone().or_else(|| self.orig.clone()).ok_or(DynamicValueEmptyError)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum Planets {
+    #[default]
+    TwoPlanets,
+    ThreePlanets,
+}
+impl Planets {
+    pub const fn count(&self) -> usize {
+        match self {
+            Planets::TwoPlanets => 2,
+            Planets::ThreePlanets => 3,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -74,7 +93,6 @@ pub enum TrackColorPulse {
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
 pub enum TrackAnimation {
-    // TODO
     #[default]
     None,
     Fade,
@@ -88,7 +106,6 @@ pub enum TrackAnimation {
 }
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
 pub enum TrackDisappearAnimation {
-    // TODO
     #[default]
     None,
     Fade,
@@ -127,6 +144,9 @@ pub enum Hitsound {
 #[getset(get = "pub")]
 pub struct TileData {
     pub(crate) orbit: Option<Orbit>,
+    pub(crate) planets: Option<Planets>,
+    /// The index of the planet that lands on this tile: 0 is red, 1 blue and 2 green.
+    pub(crate) pivot: Option<usize>,
     pub(crate) beats: Option<f64>,
     pub(crate) seconds: Option<f64>,
     pub(crate) stick_to_floors: Option<bool>,
@@ -153,10 +173,29 @@ pub struct TileData {
     pub(crate) color_anim_duration: DynamicValue<f64>,
     pub(crate) color_pulse: DynamicValue<TrackColorPulse>,
     pub(crate) pulse_length: DynamicValue<u32>,
+    pub(crate) glow_intensity: DynamicValue<f64>,
     pub(crate) style: DynamicValue<TrackStyle>,
 
+    pub(crate) track_animation: Option<TrackAnimation>,
+    pub(crate) beats_ahead: Option<f64>,
+    pub(crate) track_disappear_animation: Option<TrackDisappearAnimation>,
+    pub(crate) beats_behind: Option<f64>,
+    /// Whether the tile has appeared and not yet disappeared, see
+    /// [`crate::TrackAnimation`] and [`crate::TrackDisappearAnimation`].
+    pub(crate) visible: DynamicValue<bool>,
 }
 impl TileData {
+    /// Takes the track colors and style of `other`.
+    pub(crate) fn copy_colors(&mut self, other: &TileData) {
+        self.color_type = other.color_type;
+        self.color = other.color;
+        self.secondary_color = other.secondary_color;
+        self.color_anim_duration = other.color_anim_duration;
+        self.color_pulse = other.color_pulse;
+        self.pulse_length = other.pulse_length;
+        self.glow_intensity = other.glow_intensity;
+        self.style = other.style;
+    }
     pub(crate) fn orig2now(&mut self) {
         self.position.orig2now();
         self.scale.orig2now();
@@ -170,7 +209,10 @@ impl TileData {
         self.color_anim_duration.orig2now();
         self.color_pulse.orig2now();
         self.pulse_length.orig2now();
+        self.glow_intensity.orig2now();
         self.style.orig2now();
+
+        self.visible.orig2now();
     }
 }
 
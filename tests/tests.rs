@@ -45,4 +45,1284 @@ fn t() {
     for tile in &level.tiles {
         println!("{}", tile.data.seconds().unwrap());
     }
-}
\ No newline at end of file
+}
+const BASIC_LEVEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/levels/basic.adofai");
+
+fn to_value<T: serde::Serialize>(value: &T) -> serde_json_lenient::Value {
+    serde_json_lenient::to_value(value).unwrap()
+}
+
+#[test]
+fn save_and_reopen_round_trip() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let path = std::env::temp_dir().join("rusty_adofai_save_and_reopen_round_trip.adofai");
+    level.save(&path).unwrap();
+    let reopened = Level::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(level.tiles.len(), reopened.tiles.len());
+    for (tile, reopened_tile) in level.tiles.iter().zip(&reopened.tiles) {
+        assert_eq!(tile.angle, reopened_tile.angle);
+        assert_eq!(to_value(&tile.events), to_value(&reopened_tile.events));
+    }
+    assert_eq!(to_value(&level.settings), to_value(&reopened.settings));
+    assert_eq!(level.to_string().unwrap(), reopened.to_string().unwrap());
+}
+
+#[test]
+fn serialized_level_skips_synthetic_first_tile() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let value = to_value(&level);
+    let path_data = value["pathData"].as_str().unwrap();
+    assert_eq!(path_data.chars().count(), level.tiles.len() - 1);
+    let options = SaveOptions {
+        angle_format: AngleFormat::AngleData,
+        ..Default::default()
+    };
+    let value: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&level.to_string_with(&options).unwrap()).unwrap();
+    let angle_data = value["angleData"].as_array().unwrap();
+    assert_eq!(angle_data.len(), level.tiles.len() - 1);
+    assert_eq!(value["actions"].as_array().unwrap().len(), 14);
+    assert_eq!(value["settings"]["trackColor"], "debb7b");
+}
+
+#[test]
+fn unknown_events_are_preserved() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let unknown: Vec<_> = level.tiles[3]
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            rusty_adofai::event::EventData::Unknown {
+                event_type,
+                floor,
+                raw_json,
+            } => Some((event_type, *floor, raw_json)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(unknown.len(), 1);
+    let (event_type, floor, raw_json) = unknown[0];
+    assert_eq!(event_type, "EditorComment");
+    assert_eq!(floor, 3);
+    assert_eq!(raw_json["comment"], "Speed up\nhere");
+    assert_eq!(level.tiles[12].events[0].floor(), 12);
+
+    let reopened: Level = serde_json_lenient::from_str(&level.to_string().unwrap()).unwrap();
+    assert_eq!(to_value(&level.tiles[3].events), to_value(&reopened.tiles[3].events));
+    assert_eq!(to_value(&level.tiles[12].events), to_value(&reopened.tiles[12].events));
+}
+
+#[test]
+fn unknown_settings_are_preserved_in_order() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let reopened: Level = serde_json_lenient::from_str(&level.to_string().unwrap()).unwrap();
+    let settings = &reopened.settings;
+    assert_eq!(settings.preview_image, "preview.png");
+    assert_eq!(settings.level_tags, "tag1,tag2");
+    assert!(!settings.legacy_flash);
+    assert_eq!(settings.required_mods, ["Mod A", "Mod B"]);
+    let keys: Vec<&String> = settings.extra.keys().collect();
+    assert_eq!(keys, ["customKeyA", "customKeyB"]);
+    assert_eq!(settings.extra["customKeyB"]["nested"][1], 2);
+    let value = to_value(settings);
+    let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+    let position = |key: &str| keys.iter().position(|k| *k == key).unwrap();
+    assert!(position("previewImage") < position("levelTags"));
+    assert!(position("legacyFlash") < position("customKeyA"));
+    assert!(position("customKeyA") < position("customKeyB"));
+}
+
+#[test]
+fn settings_metadata_defaults() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let settings = &level.settings;
+    assert_eq!(settings.difficulty, 1);
+    assert_eq!(settings.special_artist_type, SpecialArtistType::None);
+    assert_eq!(settings.preview_song, None);
+    assert_eq!(settings.preview_song_duration, 10.0);
+    assert_eq!(settings.parallax, rusty_adofai::vector2d::Vector2D::new(100.0, 100.0));
+    assert_eq!(settings.bg_display_mode, BgDisplayMode::FitToScreen);
+    assert!(settings.show_default_bg_if_no_image);
+    assert_eq!(settings.default_text_shadow_color, rgb::Rgba::new(0, 0, 0, 0x50));
+    assert_eq!(settings.planet_ease_parts, 1);
+    assert!(to_value(settings).get("previewSong").is_none());
+
+    let mut json: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&fs::read_to_string(BASIC_LEVEL).unwrap()).unwrap();
+    let settings = json["settings"].as_object_mut().unwrap();
+    settings.insert("specialArtistType".to_string(), "AuthorIsArtist".into());
+    settings.insert("difficulty".to_string(), 7.into());
+    settings.insert("bgDisplayMode".to_string(), "Tiled".into());
+    settings.insert("loopBG".to_string(), "Enabled".into());
+    settings.insert("defaultBGShapeType".to_string(), "SingleColor".into());
+    settings.insert("planetEase".to_string(), "InOutSine".into());
+    settings.insert("planetEasePartBehavior".to_string(), "Repeat".into());
+    settings.insert("disableV15Features".to_string(), true.into());
+    settings.insert("previewSong".to_string(), "preview.ogg".into());
+    let level = Level::from_json(&json).unwrap();
+    let settings = &level.settings;
+    assert_eq!(settings.special_artist_type, SpecialArtistType::AuthorIsArtist);
+    assert_eq!(settings.difficulty, 7);
+    assert_eq!(settings.bg_display_mode, BgDisplayMode::Tiled);
+    assert!(settings.loop_bg);
+    assert_eq!(settings.default_bg_shape_type, BgShapeType::SingleColor);
+    assert_eq!(settings.planet_ease_part_behavior, EasePartBehavior::Repeat);
+    assert!(settings.disable_v15_features);
+    assert_eq!(settings.preview_song.as_deref(), Some("preview.ogg"));
+    assert!(settings.extra.keys().all(|key| key.starts_with("customKey")));
+    let value = to_value(settings);
+    assert_eq!(value["loopBG"], true);
+    assert_eq!(value["disableV15Features"], true);
+    assert_eq!(value["planetEase"], "InOutSine");
+}
+
+fn load_error(json: &str) -> LevelLoadError {
+    let json = serde_json_lenient::from_str(json).unwrap();
+    Level::from_json(&json).unwrap_err()
+}
+
+#[test]
+fn load_errors_carry_their_location() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<LevelLoadError>();
+
+    let level = fs::read_to_string(BASIC_LEVEL).unwrap();
+    let mut json: serde_json_lenient::Value = serde_json_lenient::from_str(&level).unwrap();
+
+    json["settings"]["trackColor"] = "not a color".into();
+    let err = load_error(&json.to_string());
+    assert!(matches!(&err, LevelLoadError::InvalidSettings { field, .. } if field == "trackColor"));
+    assert_eq!(err.location(), "settings.trackColor");
+
+    json["settings"]["trackColor"] = "debb7b".into();
+    json["settings"].as_object_mut().unwrap().remove("bpm");
+    let err = load_error(&json.to_string());
+    assert!(matches!(&err, LevelLoadError::InvalidSettings { field, .. } if field == "bpm"));
+
+    json["settings"]["bpm"] = 100.into();
+    json["actions"][4]["floor"] = 100.into();
+    let err = load_error(&json.to_string());
+    assert!(matches!(err, LevelLoadError::FloorOutOfRange { index: 4, floor: 100, .. }));
+    assert_eq!(err.location(), "actions[4]");
+
+    json["actions"][4]["floor"] = "seven".into();
+    let err = load_error(&json.to_string());
+    assert!(matches!(
+        &err,
+        LevelLoadError::InvalidAction { index: 4, floor: None, event_type: Some(event_type), .. }
+            if event_type == "ColorTrack"
+    ));
+
+    json.as_object_mut().unwrap().remove("angleData");
+    json["pathData"] = "RRUx?".into();
+    let err = load_error(&json.to_string());
+    assert!(matches!(err, LevelLoadError::InvalidPathChar { index: 4, path: '?' }));
+    assert_eq!(err.location(), "pathData[4]");
+}
+
+#[test]
+fn level_constructors_handle_encodings() {
+    use std::str::FromStr;
+
+    let err = Level::open("this/level/does/not/exist.adofai").unwrap_err();
+    assert!(matches!(err, LevelLoadError::Io(_)));
+
+    let text = fs::read_to_string(BASIC_LEVEL).unwrap();
+    let expected = Level::from_str(&text).unwrap().to_string().unwrap();
+
+    let mut utf8_bom = "\u{feff}".as_bytes().to_vec();
+    utf8_bom.extend_from_slice(text.as_bytes());
+    let mut utf16_le_bom = vec![0xFF, 0xFE];
+    utf16_le_bom.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    let utf16_be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    for bytes in [text.as_bytes(), &utf8_bom, &utf16_le_bom, &utf16_be] {
+        assert_eq!(Level::from_bytes(bytes).unwrap().to_string().unwrap(), expected);
+        assert_eq!(Level::from_reader(bytes).unwrap().to_string().unwrap(), expected);
+    }
+
+    let err = Level::from_bytes(b"{\"settings\": \"\xFF\xFE\xFD\"}").unwrap_err();
+    assert!(matches!(err, LevelLoadError::InvalidUtf8(_)));
+}
+
+fn load_and_parse(text: &str) {
+    use std::str::FromStr;
+    if let Ok(mut level) = Level::from_str(text) {
+        let _ = level.parse();
+    }
+}
+
+#[test]
+fn malformed_levels_do_not_panic() {
+    let text = fs::read_to_string(BASIC_LEVEL).unwrap();
+    let json: serde_json_lenient::Value = serde_json_lenient::from_str(&text).unwrap();
+    let with = |edit: &dyn Fn(&mut serde_json_lenient::Value)| {
+        let mut json = json.clone();
+        edit(&mut json);
+        json.to_string()
+    };
+    let repeat = |json: &mut serde_json_lenient::Value, repeat: serde_json_lenient::Value| {
+        json["actions"].as_array_mut().unwrap().push(repeat);
+    };
+    let cases = [
+        with(&|json| json["angleData"] = serde_json_lenient::json!([])),
+        with(&|json| {
+            json["angleData"] = serde_json_lenient::json!([999, 999, 999, 90, 999, 999]);
+            json["actions"] = serde_json_lenient::json!([]);
+        }),
+        with(&|json| json["actions"][0]["floor"] = 10_000.into()),
+        with(&|json| {
+            repeat(json, serde_json_lenient::json!({
+                "floor": 18, "eventType": "RepeatEvents", "repeatType": "Floor", "repetitions": 1,
+                "floorCount": 4_000_000_000u32, "interval": 1, "executeOnCurrentFloor": true, "tag": "t"
+            }));
+            repeat(json, serde_json_lenient::json!({
+                "floor": 18, "eventType": "MoveTrack", "startTile": [0, "ThisTile"],
+                "endTile": [-9_223_372_036_854_775_808i64, "End"], "duration": 1,
+                "positionOffset": [1, null], "angleOffset": 0, "ease": "Linear", "eventTag": "t"
+            }));
+        }),
+        with(&|json| {
+            repeat(json, serde_json_lenient::json!({
+                "floor": 17, "eventType": "RepeatEvents", "repeatType": "Floor", "repetitions": 1,
+                "interval": 1, "executeOnCurrentFloor": false, "tag": "t"
+            }));
+        }),
+        with(&|json| {
+            repeat(json, serde_json_lenient::json!({
+                "floor": 17, "eventType": "RepeatEvents", "repeatType": "Beat",
+                "repetitions": 4_294_967_295u32, "interval": 1, "executeOnCurrentFloor": false, "tag": ""
+            }));
+        }),
+    ];
+    for case in &cases {
+        load_and_parse(case);
+    }
+
+    let mut level: Level = cases[1].parse().unwrap();
+    level.parse().unwrap();
+    assert!(level.tiles.iter().all(|tile| tile.data.beats().unwrap().is_finite()));
+    for case in &cases[3..5] {
+        let mut level: Level = case.parse().unwrap();
+        level.parse().unwrap();
+        assert_eq!(level.diagnostics().len(), 1);
+    }
+
+    // Deterministic byte-level mutations of a valid level.
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    for _ in 0..500 {
+        let mut bytes = text.as_bytes().to_vec();
+        for _ in 0..(next() % 8 + 1) {
+            let index = (next() % bytes.len() as u64) as usize;
+            bytes[index] = b"{}[]\",:0123456789-.e!"[(next() % 21) as usize];
+        }
+        if let Ok(mut level) = Level::from_bytes(&bytes) {
+            let _ = level.parse();
+        }
+    }
+}
+
+#[test]
+fn short_vector_arrays_are_errors() {
+    let text = fs::read_to_string(BASIC_LEVEL).unwrap();
+    let json: serde_json_lenient::Value = serde_json_lenient::from_str(&text).unwrap();
+
+    let mut settings = json.clone();
+    settings["settings"]["position"] = serde_json_lenient::json!([]);
+    assert!(Level::from_json(&settings).is_err());
+
+    for action in [
+        serde_json_lenient::json!({ "floor": 1, "eventType": "PositionTrack",
+            "positionOffset": [], "editorOnly": false }),
+        serde_json_lenient::json!({ "floor": 1, "eventType": "MoveCamera", "duration": 1,
+            "position": [1], "angleOffset": 0, "ease": "Linear", "eventTag": "" }),
+    ] {
+        let mut json = json.clone();
+        json["actions"] = serde_json_lenient::json!([action]);
+        let level = Level::from_json(&json).unwrap();
+        assert!(matches!(level.tiles[1].events[0], rusty_adofai::event::EventData::Unknown { .. }));
+    }
+
+    let mut decorations = json.clone();
+    decorations["decorations"] = serde_json_lenient::json!([
+        { "eventType": "AddDecoration", "position": [] },
+    ]);
+    let level = Level::from_json(&decorations).unwrap();
+    assert!(matches!(level.decorations[0], DecorationData::Unknown { .. }));
+}
+
+fn basic_json_with(
+    angle_data: serde_json_lenient::Value,
+    actions: serde_json_lenient::Value,
+) -> serde_json_lenient::Value {
+    let mut json: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&fs::read_to_string(BASIC_LEVEL).unwrap()).unwrap();
+    json["angleData"] = angle_data;
+    json["actions"] = actions;
+    json
+}
+
+fn basic_level_with(
+    angle_data: serde_json_lenient::Value,
+    actions: serde_json_lenient::Value,
+) -> Level {
+    Level::from_json(&basic_json_with(angle_data, actions)).unwrap()
+}
+
+fn close(a: vector2d::Vector2D<f64>, x: f64, y: f64) -> bool {
+    (a.x - x).abs() < 1e-9 && (a.y - y).abs() < 1e-9
+}
+
+#[test]
+fn tempo_map_conversions() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 3, "eventType": "SetSpeed", "speedType": "Bpm", "beatsPerMinute": 240, "bpmMultiplier": 1, "angleOffset": 0 },
+            { "floor": 4, "eventType": "MoveCamera", "duration": 1, "angleOffset": 90, "ease": "Linear", "eventTag": "" },
+            { "floor": 5, "eventType": "SetSpeed", "speedType": "Multiplier", "beatsPerMinute": 100, "bpmMultiplier": 0.5, "angleOffset": 0 },
+        ]),
+    );
+    level.parse().unwrap();
+    let seconds: Vec<f64> = level.tiles.iter().map(|tile| tile.data.seconds().unwrap()).collect();
+    let expected = [-1.5, 0.5, 1.0, 1.5, 1.75, 2.0, 2.5, 3.0, 3.5, 4.0, 4.5];
+    for (seconds, expected) in seconds.iter().zip(expected) {
+        assert!((seconds - expected).abs() < 1e-9, "{} != {}", seconds, expected);
+    }
+    let rusty_adofai::event::EventData::Dynamic { beats, seconds, .. } = level.tiles[4].events[0] else {
+        panic!()
+    };
+    assert_eq!(beats, Some(3.5));
+    assert!((seconds.unwrap() - 1.875).abs() < 1e-9);
+
+    assert_eq!(level.get_bpm_by_beats(2.0).unwrap(), 240.0);
+    assert_eq!(level.get_bpm_excluding_beats(2.0).unwrap(), 120.0);
+    assert_eq!(level.get_bpm_by_seconds(2.1).unwrap(), 120.0);
+    assert_eq!(level.get_bpm_by_floor_seconds(4, 2.1).unwrap(), 240.0);
+    assert_eq!(level.get_floor_by_seconds(1.8).unwrap(), 4);
+    assert_eq!(level.get_floor_by_seconds(-10.0).unwrap(), 0);
+    assert_eq!(level.get_floor_by_seconds(100.0).unwrap(), 10);
+
+    // Segments are sorted by beats, so a later floor can come first.
+    let set_speed = |bpm: f64| -> rusty_adofai::event::SetSpeed {
+        serde_json_lenient::from_value(json!({
+            "floor": 0, "speedType": "Bpm", "beatsPerMinute": bpm, "bpmMultiplier": 1, "angleOffset": 0
+        }))
+        .unwrap()
+    };
+    let mut tempo_map = TempoMap::new(100.0, 0.0, 1.0);
+    tempo_map.push(set_speed(200.0), 5, 1.0);
+    tempo_map.push(set_speed(300.0), 2, 2.0);
+    assert_eq!(tempo_map.bpm_by_floor_seconds(2, 10.0), 300.0);
+    assert_eq!(tempo_map.bpm_by_floor_seconds(4, 0.8), 100.0);
+    // Like walking the tiles, the floor 2 SetSpeed that has not started yet stops the walk.
+    assert_eq!(tempo_map.bpm_by_floor_seconds(5, 0.8), 100.0);
+    // A Multiplier multiplies the BPM of the floors before it, not of the skipped later floor.
+    let mut multiplier = set_speed(0.0);
+    multiplier.speed_type = rusty_adofai::event::SpeedType::Multiplier;
+    multiplier.bpm_multiplier = 0.5;
+    let mut tempo_map = TempoMap::new(100.0, 0.0, 1.0);
+    tempo_map.push(set_speed(200.0), 5, 1.0);
+    tempo_map.push(multiplier, 2, 2.0);
+    assert_eq!(tempo_map.bpm_by_floor_seconds(2, 10.0), 50.0);
+    assert_eq!(tempo_map.bpm_by_floor_seconds(4, 0.8), 100.0);
+    assert_eq!(tempo_map.bpm_by_floor_seconds(5, 10.0), 200.0);
+}
+
+#[test]
+fn large_level_parses_and_converts() {
+    use serde_json_lenient::json;
+    let tiles = 10_000;
+    let angle_data: Vec<f64> = (0..tiles).map(|i| (i % 4) as f64 * 90.0).collect();
+    let actions: Vec<serde_json_lenient::Value> = (1..tiles)
+        .step_by(10)
+        .map(|floor| {
+            json!({
+                "floor": floor, "eventType": "SetSpeed", "speedType": "Bpm",
+                "beatsPerMinute": 100 + floor % 200, "bpmMultiplier": 1, "angleOffset": 0
+            })
+        })
+        .collect();
+    let mut level = basic_level_with(json!(angle_data), json!(actions));
+    level.parse().unwrap();
+    assert_eq!(level.tempo_map().segments().len(), actions.len());
+    for (floor, tile) in level.tiles.iter().enumerate().step_by(97) {
+        let seconds = tile.data.seconds().unwrap();
+        let beats = level.seconds2beats(seconds).unwrap();
+        assert!((beats - tile.data.beats().unwrap()).abs() < 1e-6);
+        assert_eq!(level.get_floor_by_seconds(seconds).unwrap(), floor);
+    }
+}
+
+#[test]
+fn pitch_and_playback_rate_scale_timing() {
+    use serde_json_lenient::json;
+    let angle_data = json!([0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let actions = json!([
+        { "floor": 3, "eventType": "SetSpeed", "speedType": "Bpm", "beatsPerMinute": 240, "bpmMultiplier": 1, "angleOffset": 0 },
+        { "floor": 5, "eventType": "SetSpeed", "speedType": "Multiplier", "beatsPerMinute": 100, "bpmMultiplier": 0.5, "angleOffset": 0 },
+    ]);
+    let mut normal = basic_level_with(angle_data.clone(), actions.clone());
+    normal.parse().unwrap();
+    let mut level = basic_level_with(angle_data, actions);
+    level.settings.pitch = 200.0;
+    level.parse().unwrap();
+    assert_eq!(level.speed(), 2.0);
+    for (tile, normal_tile) in level.tiles.iter().zip(&normal.tiles) {
+        let (seconds, normal_seconds) = (tile.data.seconds().unwrap(), normal_tile.data.seconds().unwrap());
+        assert!((seconds * 2.0 - normal_seconds).abs() < 1e-9, "{} != {} / 2", seconds, normal_seconds);
+    }
+    assert_eq!(level.get_bpm_by_beats(2.0).unwrap(), 480.0);
+    assert_eq!(level.get_bpm_by_beats(5.0).unwrap(), 240.0);
+    assert_eq!(level.seconds2beats(level.beats2seconds(4.25).unwrap()).unwrap(), 4.25);
+    assert_eq!(level.seconds2song_seconds(0.25), 0.5);
+    assert_eq!(level.song_seconds2seconds(0.5), 0.25);
+    let (perfect, _, _) = level.get_hit_margin_bound(1, Difficulty::Strict).unwrap();
+    let (normal_perfect, _, _) = normal.get_hit_margin_bound(1, Difficulty::Strict).unwrap();
+    assert!((perfect * 2.0 - normal_perfect).abs() < 1e-9);
+
+    level.set_playback_rate(0.5);
+    assert!(!level.parsed());
+    level.parse().unwrap();
+    assert_eq!(level.speed(), 1.0);
+    for (tile, normal_tile) in level.tiles.iter().zip(&normal.tiles) {
+        let (seconds, normal_seconds) = (tile.data.seconds().unwrap(), normal_tile.data.seconds().unwrap());
+        assert!((seconds - normal_seconds).abs() < 1e-9, "{} != {}", seconds, normal_seconds);
+    }
+}
+
+#[test]
+fn relative_path_data() {
+    use serde_json_lenient::json;
+    let angles = path_data2angles("RU5tL!h").unwrap();
+    let expected = [0.0, 90.0, 162.0, 282.0, 180.0, 999.0, 60.0];
+    for (angle, expected) in angles.iter().zip(expected) {
+        assert!((angle - expected).abs() < 1e-9, "{} != {}", angle, expected);
+    }
+    assert_eq!(path_data2angles("R7?"), Err((2, '?')));
+    assert_eq!(angles2path_data(&angles, DEFAULT_ANGLE_EPSILON).as_deref(), Ok("RU5tL!T"));
+    assert_eq!(angles2path_data(&[0.0, 10.0], DEFAULT_ANGLE_EPSILON), Err(1));
+
+    // A heptagon: seven '7' turns return to the first direction.
+    let heptagon = path_data2angles("R7777777").unwrap();
+    assert!((heptagon[7] - heptagon[0]).abs() < 1e-9 || (heptagon[7] - 360.0).abs() < 1e-9);
+    assert_eq!(angles2path_data(&heptagon, DEFAULT_ANGLE_EPSILON).as_deref(), Ok("R777777R"));
+
+    let mut json: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&fs::read_to_string(BASIC_LEVEL).unwrap()).unwrap();
+    let object = json.as_object_mut().unwrap();
+    object.remove("angleData");
+    object.insert("pathData".to_string(), json!("RU5tL!h"));
+    object.insert("actions".to_string(), json!([]));
+    let mut level = Level::from_json(&json).unwrap();
+    level.parse().unwrap();
+    let beats: Vec<f64> = level.tiles.iter().map(|tile| tile.data.beats().unwrap()).collect();
+    assert!((beats[3] - beats[2] - 0.6).abs() < 1e-9);
+    assert!((beats[4] - beats[3] - 1.0 / 3.0).abs() < 1e-9);
+    assert!((beats[7] - beats[6] - 2.0 / 3.0).abs() < 1e-9);
+
+    let value = to_value(&level);
+    assert_eq!(value["pathData"], json!("RU5tL!T"));
+    assert!(value.get("angleData").is_none());
+    level.tiles[2].angle = 10.0;
+    assert!(to_value(&level).get("angleData").is_some());
+}
+
+#[test]
+fn save_chooses_angle_format() {
+    use serde_json_lenient::json;
+    assert_eq!(angle2path(89.99999, DEFAULT_ANGLE_EPSILON), Some('U'));
+    assert_eq!(angle2path(359.99999, DEFAULT_ANGLE_EPSILON), Some('R'));
+    assert_eq!(angle2path(89.99999, 0.0), None);
+    assert_eq!(angle2path(999.0, 0.0), Some('!'));
+    assert_eq!(angle2path(89.9, DEFAULT_ANGLE_EPSILON), None);
+
+    let save = |level: &Level, angle_format| {
+        let options = SaveOptions {
+            angle_format,
+            ..Default::default()
+        };
+        level
+            .to_string_with(&options)
+            .map(|string| serde_json_lenient::from_str::<serde_json_lenient::Value>(&string).unwrap())
+    };
+    let mut level = basic_level_with(json!([0, 89.99999, 180.00001, 999, 45]), json!([]));
+    let value = save(&level, AngleFormat::Auto).unwrap();
+    assert_eq!(value["pathData"], "RUL!E");
+    assert!(value.get("angleData").is_none());
+    let value = save(&level, AngleFormat::AngleData).unwrap();
+    assert_eq!(value["angleData"][1], 89.99999);
+    assert!(value.get("pathData").is_none());
+    // Source keeps the angleData of the source, and the pathData of a pathData source.
+    let value = save(&level, AngleFormat::Source).unwrap();
+    assert_eq!(value["angleData"][1], 89.99999);
+    assert!(value.get("pathData").is_none());
+    let path_data_level = Level::from_json(&save(&level, AngleFormat::PathData).unwrap()).unwrap();
+    assert_eq!(save(&path_data_level, AngleFormat::Source).unwrap()["pathData"], "RUL!E");
+
+    level.tiles[2].angle = 10.0;
+    let value = save(&level, AngleFormat::Auto).unwrap();
+    assert_eq!(value["angleData"][1], 10.0);
+    assert!(save(&level, AngleFormat::PathData).is_err());
+
+    let options = SaveOptions {
+        angle_format: AngleFormat::PathData,
+        angle_epsilon: 0.5,
+        ..Default::default()
+    };
+    level.tiles[2].angle = 89.6;
+    assert!(level.to_string_with(&options).unwrap().contains("\"RUL!E\""));
+}
+
+#[test]
+fn saving_keeps_source_keys() {
+    let text = fs::read_to_string(BASIC_LEVEL)
+        .unwrap()
+        .replace("\t\t\"countdownTicks\": 4, \n", "");
+    let keys = |value: &serde_json_lenient::Value| -> Vec<String> {
+        value["settings"].as_object().unwrap().keys().cloned().collect()
+    };
+    let source: serde_json_lenient::Value = serde_json_lenient::from_str(&text).unwrap();
+    let mut level: Level = text.parse().unwrap();
+    let saved: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&level.to_string().unwrap()).unwrap();
+    assert_eq!(keys(&saved), keys(&source));
+    let action_keys = |value: &serde_json_lenient::Value| -> Vec<Vec<String>> {
+        let actions = value["actions"].as_array().unwrap();
+        actions.iter().map(|action| action.as_object().unwrap().keys().cloned().collect()).collect()
+    };
+    assert_eq!(action_keys(&saved), action_keys(&source));
+
+    level.settings.countdown_ticks = 8;
+    let saved: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&level.to_string().unwrap()).unwrap();
+    assert_eq!(keys(&saved)[..keys(&source).len()], keys(&source));
+    assert_eq!(keys(&saved).last().unwrap(), "countdownTicks");
+    assert_eq!(saved["settings"]["customKeyA"], 1);
+}
+
+#[test]
+fn lossless_round_trip() {
+    use rusty_adofai::event::*;
+    let text = fs::read_to_string(BASIC_LEVEL)
+        .unwrap()
+        .replace("\"angleData\": [0, 0, 90, 180, 180, 999, 0, 45, 90, 90, 270, 0, 0, 0, 315, 0, 0, 0, 0, 0], ",
+            "\"angleData\": [0, 0, 90, 180, 180, 999, 0, 45, 90, 90, 270, 0, 0, 0, 315, 0, 0, 0, 0, 0], // tiles")
+        .replace("\"requiredMods\": [\"Mod A\", \"Mod B\"] ", "\"requiredMods\": [\"Mod A\", \"Mod B\"], /* trailing */")
+        .replace("\t\t{ \"floor\": 2, \"eventType\": \"Twirl\" },\n", "")
+        .replace("\"tag\": \"move\" }\n", "\"tag\": \"move\" },\n\t\t{ \"floor\": 2, \"eventType\": \"Twirl\" },\n");
+    let text = format!("\u{feff}{}", text);
+    let mut level = Level::from_str_lossless(&text).unwrap();
+    assert!(level.is_lossless());
+    assert_eq!(level.to_string().unwrap(), text);
+
+    let changed_lines = |output: &str| -> Vec<String> {
+        let original: Vec<&str> = text.lines().collect();
+        output
+            .lines()
+            .filter(|line| !original.contains(line))
+            .map(|line| line.to_string())
+            .collect()
+    };
+
+    level.settings.bpm = 150.5;
+    let output = level.to_string().unwrap();
+    assert_eq!(output, text.replace("\"bpm\": 120,", "\"bpm\": 150.5,"));
+
+    level.settings.bpm = 120.0;
+    level.tiles[3].angle = 135.0;
+    let output = level.to_string().unwrap();
+    assert_eq!(output, text.replace("[0, 0, 90, 180,", "[0, 0, 135, 180,"));
+
+    level.tiles[3].angle = 90.0;
+    let EventData::Dynamic { event: DynamicEvents::SetSpeed(set_speed), .. } = &mut level.tiles[3].events[0] else {
+        panic!()
+    };
+    set_speed.beats_per_minute = 300.0;
+    let output = level.to_string().unwrap();
+    let changed = changed_lines(&output);
+    assert_eq!(changed.len(), 1);
+    assert!(changed[0].contains("\"beatsPerMinute\": 300,"));
+    assert!(changed[0].contains("\"bpmMultiplier\": 1,"));
+    assert_eq!(output.lines().count(), text.lines().count());
+
+    // Remove the Bookmark and add a Twirl.
+    level.tiles[12].events.remove(0);
+    let twirl: StaticEvents = serde_json_lenient::from_str(r#"{ "eventType": "Twirl", "floor": 5 }"#).unwrap();
+    level.tiles[5].events.push(EventData::Static { event: twirl });
+    let output = level.to_string().unwrap();
+    assert!(!output.contains("Bookmark"));
+    assert_eq!(output.lines().count(), text.lines().count());
+    assert_eq!(changed_lines(&output).len(), 2);
+    let reopened = Level::from_str_lossless(&output).unwrap();
+    assert_eq!(to_value(&level), to_value(&reopened));
+    assert_eq!(reopened.to_string().unwrap(), output);
+
+    level.settings.extra.shift_remove("customKeyA");
+    level.settings.extra.insert("newKey".to_string(), serde_json_lenient::json!(2));
+    let output = level.to_string().unwrap();
+    assert!(!output.contains("customKeyA"));
+    assert!(output.contains("\"newKey\": 2"));
+    let reopened = Level::from_str_lossless(&output).unwrap();
+    assert_eq!(to_value(&level), to_value(&reopened));
+
+    let options = SaveOptions {
+        angle_format: AngleFormat::PathData,
+        ..Default::default()
+    };
+    let output = level.to_string_with(&options).unwrap();
+    assert!(output.contains("\t\"pathData\": \"RRULL!REUUDRRRCRRRRR\", // tiles"));
+    assert!(!output.contains("angleData"));
+}
+
+#[test]
+fn game_style_formatting() {
+    let text = fs::read_to_string(BASIC_LEVEL).unwrap();
+    let json: serde_json_lenient::Value = serde_json_lenient::from_str(&text).unwrap();
+    assert_eq!(format_game_style(&json), text);
+
+    let with_decorations = text.replace(
+        "\n\t]\n}",
+        "\n\t],\n\t\"decorations\":\n\t[\n\t\t{ \"floor\": 1, \"eventType\": \"AddDecoration\", \"tag\": \"a\" }\n\t]\n}",
+    );
+    let json: serde_json_lenient::Value = serde_json_lenient::from_str(&with_decorations).unwrap();
+    assert_eq!(format_game_style(&json), with_decorations);
+
+    let options = SaveOptions {
+        output_format: OutputFormat::Game,
+        angle_format: AngleFormat::Source,
+        ..Default::default()
+    };
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    let output = level.to_string_with(&options).unwrap();
+    assert_eq!(output, format!("\u{feff}{}", text));
+    let reopened: Level = output.parse().unwrap();
+    assert_eq!(to_value(&level), to_value(&reopened));
+}
+
+#[test]
+fn old_versions_are_migrated() {
+    let level = Level::open(BASIC_LEVEL).unwrap();
+    assert!(level.migrations().is_empty());
+
+    let text = fs::read_to_string(BASIC_LEVEL)
+        .unwrap()
+        .replace("\"version\": 15 ,", "\"version\": 1 ,")
+        .replace("\t\t\"countdownTicks\": 4, \n", "")
+        .replace("\"justThisTile\": false", "\"justThisTile\": \"Disabled\"");
+    let mut level = Level::from_str_lossless(&text).unwrap();
+    let versions: Vec<u32> = level.migrations().iter().map(|migration| migration.version).collect();
+    assert_eq!(versions, [2, 4, 4, 6, 9, 13, LATEST_VERSION]);
+    assert_eq!(level.settings.version, LATEST_VERSION);
+    assert_eq!(level.settings.countdown_ticks, 4);
+    // The legacy flags are on unless the level sets them, as it does legacyFlash.
+    assert!(!level.settings.legacy_flash && level.settings.legacy_cam_relative_to);
+    assert!(level.settings.legacy_sprite_tiles && level.settings.legacy_tween);
+    level.parse().unwrap();
+    assert_eq!(*level.tiles[0].data.beats(), Some(-4.0));
+    // The migrated content is saved with the version it follows.
+    let output = level.to_string().unwrap();
+    assert!(output.contains("\"version\": 15 ,"));
+    assert!(output.contains("\"justThisTile\": false"));
+    assert!(!output.contains("Disabled"));
+    let reopened = Level::from_str_lossless(&output).unwrap();
+    assert!(reopened.migrations().is_empty());
+    assert_eq!(reopened.settings.countdown_ticks, 4);
+
+    let level: Level = text.parse().unwrap();
+    let value: serde_json_lenient::Value =
+        serde_json_lenient::from_str(&level.to_string().unwrap()).unwrap();
+    assert_eq!(value["settings"]["version"], 15);
+    assert_eq!(value["settings"]["legacyTween"], true);
+    let mut level = Level::open(BASIC_LEVEL).unwrap();
+    level.settings.version = 10;
+    let options = SaveOptions {
+        latest_version: true,
+        ..Default::default()
+    };
+    assert!(level.to_string_with(&options).unwrap().contains("\"version\": 15"));
+
+    let mut json: serde_json_lenient::Value = serde_json_lenient::from_str(
+        r#"{ "settings": { "version": 1, "loopBG": "Enabled", "artist": "Enabled" },
+            "actions": [{ "floor": 1, "eventType": "MoveTrack", "startTile": 0, "endTile": 2 },
+                { "floor": 2, "eventType": "Custom", "mode": "Enabled" }],
+            "decorations": [{ "eventType": "AddDecoration", "lockRotation": "Disabled", "tag": "Enabled" }] }"#,
+    )
+    .unwrap();
+    assert_eq!(migrate(&mut json).len(), 7);
+    assert_eq!(json["decorations"][0]["lockRotation"], false);
+    assert_eq!(json["decorations"][0]["tag"], "Enabled");
+    assert_eq!(json["settings"]["loopBG"], true);
+    assert_eq!(json["settings"]["artist"], "Enabled");
+    assert_eq!(json["actions"][0]["startTile"], serde_json_lenient::json!([0, "ThisTile"]));
+    assert_eq!(json["actions"][0]["positionOffset"], serde_json_lenient::json!([null, null]));
+    // Only known boolean keys are converted.
+    assert_eq!(json["actions"][1]["mode"], "Enabled");
+    assert_eq!(json["settings"]["legacyCamRelativeTo"], true);
+    assert_eq!(json["settings"]["version"], LATEST_VERSION);
+}
+
+#[test]
+fn decorations_are_modelled() {
+    let text = fs::read_to_string(BASIC_LEVEL).unwrap().replace(
+        "\n\t]\n}",
+        concat!(
+            "\n\t],\n\t\"decorations\":\n\t[\n",
+            "\t\t{ \"floor\": 3, \"eventType\": \"AddDecoration\", \"decorationImage\": \"star.png\", \"position\": [1, 2], \"relativeTo\": \"Tile\", \"pivotOffset\": [0, 0], \"rotation\": 30, \"lockRotation\": false, \"scale\": [50, 50], \"lockScale\": false, \"tile\": [2, 1], \"color\": \"ff000080\", \"opacity\": 50, \"depth\": 5, \"parallax\": [10, 10], \"parallaxOffset\": [0, 0], \"tag\": \"star sky\", \"imageSmoothing\": true, \"blendMode\": \"None\", \"hitbox\": \"Kill\", \"hitboxEventTag\": \"\", \"failHitboxType\": \"Circle\", \"failHitboxScale\": [100, 100], \"failHitboxOffset\": [0, 0], \"failHitboxRotation\": 0 },\n",
+            "\t\t{ \"eventType\": \"AddText\", \"decText\": \"Hello\", \"font\": \"Arial\", \"position\": [0, 3], \"relativeTo\": \"Camera\", \"tag\": \"text\" },\n",
+            "\t\t{ \"eventType\": \"AddObject\", \"objectType\": \"Planet\", \"relativeTo\": \"Global\", \"planetColor\": \"ff0000\" },\n",
+            "\t\t{ \"eventType\": \"AddParticleSystem\", \"tag\": \"dust\" }\n",
+            "\t]\n}"
+        ),
+    );
+    let mut level = Level::from_str_lossless(&text).unwrap();
+    assert_eq!(level.decorations.len(), 4);
+    let DecorationData::Known {
+        decoration: Decorations::AddDecoration(image),
+        ..
+    } = &level.decorations[0]
+    else {
+        panic!("expected an AddDecoration");
+    };
+    assert_eq!(image.floor, Some(3));
+    assert_eq!(image.decoration_image, "star.png");
+    assert_eq!((image.position.x, image.position.y), (1.0, 2.0));
+    assert_eq!((image.tile.x, image.tile.y), (2.0, 1.0));
+    assert_eq!(image.color.a, 0x80);
+    assert_eq!(image.depth, 5.0);
+    assert_eq!(image.tag, ["star", "sky"]);
+    assert_eq!(image.hitbox, Hitbox::Kill);
+    assert_eq!(image.fail_hitbox_type, FailHitboxType::Circle);
+    assert_eq!(image.extra["blendMode"], "None");
+    let DecorationData::Known {
+        decoration: Decorations::AddText(dec_text),
+        ..
+    } = &level.decorations[1]
+    else {
+        panic!("expected an AddText");
+    };
+    assert_eq!(dec_text.floor, None);
+    assert_eq!((dec_text.dec_text.as_str(), dec_text.font.as_str()), ("Hello", "Arial"));
+    assert_eq!(dec_text.relative_to, RelativeToDecoration::Camera);
+    assert_eq!((dec_text.scale.x, dec_text.opacity, dec_text.depth), (100.0, 100.0, -1.0));
+    let DecorationData::Known {
+        decoration: Decorations::AddObject(object),
+        ..
+    } = &level.decorations[2]
+    else {
+        panic!("expected an AddObject");
+    };
+    assert_eq!(object.object_type, ObjectType::Planet);
+    assert_eq!(object.planet_color, rgb::Rgba::new(0xff, 0, 0, 0xff));
+    assert_eq!(object.track_angle, 180.0);
+    assert!(matches!(&level.decorations[3], DecorationData::Unknown { event_type, .. } if event_type == "AddParticleSystem"));
+
+    let plain: Level = text.parse().unwrap();
+    let reopened: Level = plain.to_string().unwrap().parse().unwrap();
+    assert_eq!(to_value(&plain), to_value(&reopened));
+    assert_eq!(to_value(&plain), to_value(&level));
+    assert_eq!(to_value(&level)["decorations"][3], serde_json_lenient::json!({ "eventType": "AddParticleSystem", "tag": "dust" }));
+
+    assert_eq!(level.to_string().unwrap(), text);
+    let DecorationData::Known {
+        decoration: Decorations::AddText(dec_text),
+        ..
+    } = &mut level.decorations[1]
+    else {
+        unreachable!()
+    };
+    dec_text.dec_text = "Bye".to_string();
+    level.decorations.remove(3);
+    let output = level.to_string().unwrap();
+    assert_eq!(output.lines().count(), text.lines().count() - 1);
+    assert!(output.contains("\t\t{ \"eventType\": \"AddText\", \"decText\": \"Bye\", \"font\": \"Arial\""));
+    assert!(output.contains("\"planetColor\": \"ff0000\" }\n\t]\n}"));
+}
+
+#[test]
+fn decoration_events_update_state() {
+    use serde_json_lenient::json;
+    let mut json = basic_json_with(
+        json!([0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 1, "eventType": "MoveDecorations", "duration": 2, "tag": "a", "decorationImage": "b.png",
+              "positionOffset": [2, null], "rotationOffset": 90, "color": "000000", "opacity": 0, "depth": 3,
+              "angleOffset": 0, "ease": "Linear", "eventTag": "" },
+            { "floor": 2, "eventType": "SetText", "decText": "Bye", "tag": "t", "angleOffset": 0, "eventTag": "" },
+            { "floor": 2, "eventType": "SetObject", "tag": "o", "trackColor": "ffffff", "trackAngle": 90,
+              "angleOffset": 0, "eventTag": "" },
+            { "floor": 1, "eventType": "SetDefaultText", "defaultTextColor": "000000", "perfectText": "Nice",
+              "angleOffset": 0, "eventTag": "" },
+        ]),
+    );
+    json["decorations"] = json!([
+        { "eventType": "AddDecoration", "decorationImage": "a.png", "position": [1, 1], "tag": "a" },
+        { "eventType": "AddText", "decText": "Hi", "tag": "t a" },
+        { "eventType": "AddObject", "trackColor": "000000", "tag": "o" },
+    ]);
+    let mut level = Level::from_json(&json).unwrap();
+    level.parse().unwrap();
+    let state = |level: &Level, index: usize| -> DecorationState {
+        match &level.decorations[index] {
+            DecorationData::Known { data, .. } => data.clone(),
+            DecorationData::Unknown { .. } => panic!("expected a known decoration"),
+        }
+    };
+    let first = level.tiles[1].data.seconds().unwrap();
+    let second = level.tiles[2].data.seconds().unwrap();
+    let spb = second - first;
+
+    level.update(first - 0.01).unwrap();
+    let image = state(&level, 0);
+    assert_eq!(image.position().now.unwrap().x, 1.0);
+    assert_eq!(image.image().now.as_deref(), Some("a.png"));
+    assert_eq!(level.default_text().perfect_text().now.as_deref(), Some(""));
+
+    level.update(first + spb).unwrap();
+    let image = state(&level, 0);
+    assert!((image.position().now.unwrap().x - 2.0).abs() < 1e-9);
+    assert_eq!(image.position().now.unwrap().y, 1.0);
+    assert!((image.rotation().now.unwrap() - 45.0).abs() < 1e-9);
+    assert!((image.opacity().now.unwrap() - 50.0).abs() < 1e-9);
+    assert_eq!(image.color().now.unwrap().r, 128);
+    assert_eq!(image.image().now.as_deref(), Some("b.png"));
+    assert_eq!(image.depth().now, Some(3.0));
+    // The AddText shares the tag, the AddObject does not.
+    assert!((state(&level, 1).position().now.unwrap().x - 1.0).abs() < 1e-9);
+    assert_eq!(state(&level, 2).position().now.unwrap().x, 0.0);
+    assert_eq!(state(&level, 1).text().now.as_deref(), Some("Bye"));
+    let object = state(&level, 2);
+    assert_eq!(object.track_color().now.unwrap().r, 0xff);
+    assert_eq!(object.track_angle().now, Some(90.0));
+    assert_eq!(object.text().now, None);
+    assert_eq!(level.default_text().perfect_text().now.as_deref(), Some("Nice"));
+    assert_eq!(level.default_text().color().now.unwrap().r, 0);
+
+    level.update(first + 3.0 * spb).unwrap();
+    assert!((state(&level, 0).position().now.unwrap().x - 3.0).abs() < 1e-9);
+    assert_eq!(state(&level, 0).position().orig.unwrap().x, 1.0);
+
+    let value = to_value(&level);
+    assert_eq!(value["actions"][0]["eventType"], "MoveDecorations");
+    assert_eq!(value["actions"][0]["color"], "000000");
+    let reopened = Level::from_json(&value).unwrap();
+    assert_eq!(to_value(&reopened), value);
+}
+
+#[test]
+fn track_appears_and_disappears() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([{ "floor": 4, "eventType": "AnimateTrack", "trackAnimation": "Scatter", "beatsAhead": 1 }]),
+    );
+    level.settings.track_animation = TrackAnimation::Fade;
+    level.settings.beats_ahead = 3.0;
+    level.settings.track_disappear_animation = TrackDisappearAnimation::ShrinkSpin;
+    level.settings.beats_behind = 1.0;
+    level.parse().unwrap();
+    let mut data_at = |floor: usize, beats: f64| -> TileData {
+        let beats = level.tiles[floor].data.beats().unwrap() + beats;
+        level.update(level.tempo_map().beats2seconds(beats)).unwrap();
+        level.tiles[floor].data
+    };
+
+    assert_eq!(data_at(2, -3.5).visible().now, Some(false));
+    let data = data_at(2, -3.0);
+    assert_eq!(data.visible().now, Some(true));
+    assert_eq!(*data.track_animation(), Some(TrackAnimation::Fade));
+    assert_eq!(data_at(2, 1.0).visible().now, Some(true));
+    let data = data_at(2, 1.5);
+    assert_eq!(data.visible().now, Some(false));
+    assert_eq!(*data.track_disappear_animation(), Some(TrackDisappearAnimation::ShrinkSpin));
+
+    // AnimateTrack on floor 4 switches to Scatter one beat ahead.
+    let data = data_at(5, -1.0);
+    assert_eq!(data.visible().now, Some(true));
+    assert_eq!(*data.track_animation(), Some(TrackAnimation::Scatter));
+    assert_eq!(data_at(5, -1.5).visible().now, Some(false));
+    assert_eq!(data_at(3, -2.5).visible().now, Some(true));
+
+    assert_eq!(to_value(&level)["actions"][0]["trackAnimation"], "Scatter");
+
+    // Without animations every tile is always shown.
+    let mut level = basic_level_with(json!([0, 0, 0, 0, 0, 0, 0, 0]), json!([]));
+    level.settings.track_animation = TrackAnimation::None;
+    level.settings.beats_ahead = 3.0;
+    level.settings.track_disappear_animation = TrackDisappearAnimation::None;
+    level.parse().unwrap();
+    level.update(-10.0).unwrap();
+    assert_eq!(level.tiles[7].data.visible().now, Some(true));
+    level.update(100.0).unwrap();
+    assert_eq!(level.tiles[1].data.visible().now, Some(true));
+}
+
+#[test]
+fn tile_colors_are_evaluated() {
+    use serde_json_lenient::json;
+    let mut json = basic_json_with(json!([0, 0, 0, 0, 0, 0, 0, 0]), json!([]));
+    json["settings"]["trackColor"] = "ff0000".into();
+    json["settings"]["secondaryTrackColor"] = "0000ff".into();
+    json["settings"]["trackColorAnimDuration"] = 2.into();
+    json["settings"]["trackColorType"] = "Stripes".into();
+    let color = |json: &serde_json_lenient::Value, floor: usize, seconds: f64| {
+        let mut level = Level::from_json(json).unwrap();
+        level.parse().unwrap();
+        level.tile_color(floor, seconds).unwrap()
+    };
+    let red = rgb::Rgba::new(255, 0, 0, 255);
+    let blue = rgb::Rgba::new(0, 0, 255, 255);
+    let purple = rgb::Rgba::new(128, 0, 128, 255);
+
+    assert_eq!(color(&json, 2, 0.5), red);
+    assert_eq!(color(&json, 3, 0.5), blue);
+
+    json["settings"]["trackColorType"] = "Switch".into();
+    assert_eq!(color(&json, 2, 0.5), red);
+    assert_eq!(color(&json, 2, 1.5), blue);
+
+    json["settings"]["trackColorType"] = "Glow".into();
+    assert_eq!(color(&json, 2, 0.0), red);
+    assert_eq!(color(&json, 2, 1.0), blue);
+    json["settings"]["trackGlowIntensity"] = 50.into();
+    assert_eq!(color(&json, 2, 1.0), purple);
+
+    json["settings"]["trackColorType"] = "Blink".into();
+    assert_eq!(color(&json, 2, 0.0), blue);
+    assert_eq!(color(&json, 2, 1.0), purple);
+
+    // The loudness of the song is not known, so Volume stays in the track color.
+    json["settings"]["trackColorType"] = "Volume".into();
+    assert_eq!(color(&json, 2, 0.0), red);
+    assert_eq!(color(&json, 3, 1.5), red);
+
+    json["settings"]["trackColorType"] = "Rainbow".into();
+    assert_eq!(color(&json, 2, 0.0), red);
+    assert_eq!(color(&json, 2, 2.0 / 3.0), rgb::Rgba::new(0, 255, 0, 255));
+
+    // A forward pulse over 4 tiles delays each tile by a quarter of the animation.
+    json["settings"]["trackColorType"] = "Switch".into();
+    json["settings"]["trackColorPulse"] = "Forward".into();
+    json["settings"]["trackPulseLength"] = 4.into();
+    assert_eq!(color(&json, 2, 0.5), blue);
+    assert_eq!(color(&json, 2, 1.5), red);
+    assert_eq!(color(&json, 4, 0.5), red);
+    json["settings"]["trackColorPulse"] = "Backward".into();
+    assert_eq!(color(&json, 1, 0.4), red);
+    assert_eq!(color(&json, 1, 1.0), blue);
+
+    json["actions"] = json!([
+        { "floor": 5, "eventType": "ColorTrack", "trackColorType": "Single",
+          "trackColor": "00ff00", "secondaryTrackColor": "ffffff",
+          "trackColorAnimDuration": 2, "trackColorPulse": "None", "trackPulseLength": 10,
+          "trackStyle": "Standard" },
+    ]);
+    assert_eq!(color(&json, 6, 1.5), rgb::Rgba::new(0, 255, 0, 255));
+
+    let mut level = Level::from_json(&json).unwrap();
+    level.parse().unwrap();
+    assert!(level.tile_color(level.tiles.len(), 0.0).is_err());
+}
+
+#[test]
+fn track_colors_tween_and_scope() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 2, "eventType": "ColorTrack", "trackColorType": "Single",
+              "trackColor": "00ff00", "secondaryTrackColor": "00ff00",
+              "trackColorAnimDuration": 2, "trackColorPulse": "None", "trackPulseLength": 10,
+              "trackStyle": "Standard", "justThisTile": true },
+            { "floor": 4, "eventType": "RecolorTrack", "startTile": [0, "ThisTile"],
+              "endTile": [2, "ThisTile"], "gapLength": 1, "duration": 2, "ease": "Linear",
+              "trackColorType": "Single", "trackColor": "0000ff", "secondaryTrackColor": "0000ff",
+              "trackColorAnimDuration": 2, "trackColorPulse": "None", "trackPulseLength": 10,
+              "trackStyle": "Standard", "trackGlowIntensity": 50, "angleOffset": 0, "eventTag": "" },
+        ]),
+    );
+    let red = rgb::Rgba::new(255, 0, 0, 255);
+    level.settings.track_color = red;
+    level.settings.secondary_track_color = red;
+    level.parse().unwrap();
+    let green = rgb::Rgba::new(0, 255, 0, 255);
+    let blue = rgb::Rgba::new(0, 0, 255, 255);
+    let purple = rgb::Rgba::new(128, 0, 128, 255);
+
+    assert_eq!(level.tile_color(1, 0.0).unwrap(), red);
+    assert_eq!(level.tile_color(2, 0.0).unwrap(), green);
+    assert_eq!(level.tile_color(3, 0.0).unwrap(), red);
+
+    let beats4 = level.tiles[4].data.beats().unwrap();
+    let mut color_at = |floor: usize, beats: f64| {
+        let seconds = level.tempo_map().beats2seconds(beats4 + beats);
+        level.update(seconds).unwrap();
+        (level.tile_color(floor, seconds).unwrap(), level.tiles[floor].data)
+    };
+    assert_eq!(color_at(4, -0.5).0, red);
+    let (color, data) = color_at(4, 1.0);
+    assert_eq!(color, purple);
+    assert!((data.glow_intensity().now.unwrap() - 75.0).abs() < 1e-9);
+    // The next tile starts one beat later.
+    assert_eq!(color_at(5, 1.0).0, red);
+    assert_eq!(color_at(5, 2.0).0, purple);
+    assert_eq!(color_at(6, 4.0).0, blue);
+    // The colors at `seconds` do not depend on the last update.
+    let seconds = level.tempo_map().beats2seconds(beats4 + 1.0);
+    assert_eq!(level.tile_color(4, seconds).unwrap(), purple);
+    assert_eq!(level.tile_color(6, seconds).unwrap(), red);
+    assert_eq!(level.tile_color(6, 0.0).unwrap(), red);
+}
+
+#[test]
+fn move_track_staggers_tiles() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 1, "eventType": "MoveTrack", "startTile": [0, "ThisTile"],
+              "endTile": [2, "ThisTile"], "gapLength": 1, "duration": 2,
+              "positionOffset": [null, 2], "rotationOffset": 90, "opacity": 50,
+              "angleOffset": 0, "ease": "Linear", "eventTag": "" },
+            { "floor": 5, "eventType": "MoveTrack", "startTile": [0, "ThisTile"],
+              "endTile": [0, "ThisTile"], "duration": 0, "positionOffset": [3, null],
+              "angleOffset": 0, "ease": "Linear", "eventTag": "", "maxVfxOnly": true },
+        ]),
+    );
+    level.parse().unwrap();
+    let beats1 = level.tiles[1].data.beats().unwrap();
+    let mut data_at = |floor: usize, beats: f64| -> TileData {
+        level.update(level.tempo_map().beats2seconds(beats1 + beats)).unwrap();
+        level.tiles[floor].data
+    };
+
+    let data = data_at(1, 1.0);
+    assert!(close(data.position().now.unwrap(), 1.0, 1.0));
+    assert!((data.rotation().now.unwrap() - 45.0).abs() < 1e-9);
+    assert!((data.opacity().now.unwrap() - 75.0).abs() < 1e-9);
+    // The next tiles start one and two beats later.
+    assert!((data_at(2, 1.0).position().now.unwrap().y - 0.0).abs() < 1e-9);
+    assert!((data_at(2, 2.0).position().now.unwrap().y - 1.0).abs() < 1e-9);
+    assert!((data_at(3, 2.5).position().now.unwrap().y - 0.5).abs() < 1e-9);
+    assert!(close(data_at(3, 10.0).position().now.unwrap(), 3.0, 2.0));
+    assert!((data_at(4, 10.0).position().now.unwrap().y - 0.0).abs() < 1e-9);
+
+    assert!((data_at(5, 10.0).position().now.unwrap().x - 8.0).abs() < 1e-9);
+    level.set_max_vfx(false);
+    level.update(level.tempo_map().beats2seconds(beats1 + 10.0)).unwrap();
+    assert!((level.tiles[5].data.position().now.unwrap().x - 5.0).abs() < 1e-9);
+}
+
+#[test]
+fn position_track_places_tiles() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 2, "eventType": "PositionTrack", "positionOffset": [0, 1],
+              "rotation": 30, "scale": 50, "opacity": 20, "editorOnly": false },
+            { "floor": 4, "eventType": "PositionTrack", "positionOffset": [0, -2],
+              "relativeTo": [1, "Start"], "editorOnly": false, "justThisTile": true },
+            { "floor": 6, "eventType": "PositionTrack", "positionOffset": [1, 0],
+              "relativeTo": [1, "ThisTile"], "editorOnly": false },
+        ]),
+    );
+    level.parse().unwrap();
+    let position = |floor: usize| level.tiles[floor].data.position().orig.unwrap();
+
+    assert!(close(position(1), 1.0, 0.0));
+    assert!(close(position(2), 2.0, 1.0));
+    assert!(close(position(3), 3.0, 1.0));
+    // Relative to floor 1, and only for this tile.
+    assert!(close(position(4), 1.0, -2.0));
+    assert!(close(position(5), 5.0, 1.0));
+    // Relative to a tile that is not placed yet, so the offset is from this tile.
+    assert!(close(position(6), 7.0, 1.0));
+    assert_eq!(level.diagnostics().len(), 1);
+
+    let data = level.tiles[2].data;
+    assert_eq!(data.rotation().orig, Some(30.0));
+    assert_eq!(data.scale().orig.unwrap().x, 50.0);
+    assert_eq!(data.opacity().orig, Some(20.0));
+    let data = level.tiles[3].data;
+    assert_eq!(data.rotation().orig, Some(0.0));
+    assert_eq!(data.scale().orig.unwrap().y, 100.0);
+    assert_eq!(data.opacity().orig, Some(100.0));
+}
+
+#[test]
+fn screen_effects_follow_events() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 1, "eventType": "SetFilter", "filter": "Grayscale", "enabled": true,
+              "intensity": 80, "duration": 2, "ease": "Linear", "disableOthers": false,
+              "angleOffset": 0, "eventTag": "" },
+            { "floor": 1, "eventType": "SetFilterAdvanced", "filter": "SomeNewFilter",
+              "enabled": true, "duration": 0, "ease": "Linear", "disableOthers": false,
+              "filterProperties": "{}", "angleOffset": 0, "eventTag": "" },
+            { "floor": 1, "eventType": "Flash", "duration": 2, "plane": "Foreground",
+              "startColor": "ffffff", "startOpacity": 100, "endColor": "000000", "endOpacity": 0,
+              "angleOffset": 0, "ease": "Linear", "eventTag": "" },
+            { "floor": 1, "eventType": "ShakeScreen", "duration": 1, "strength": 100,
+              "intensity": 100, "ease": "Linear", "fadeOut": true, "angleOffset": 45 },
+            { "floor": 2, "eventType": "Bloom", "enabled": true, "threshold": 40, "intensity": 200,
+              "color": "ff0000", "duration": 0, "ease": "Linear", "angleOffset": 0, "eventTag": "" },
+            { "floor": 2, "eventType": "HallOfMirrors", "enabled": true, "angleOffset": 0,
+              "eventTag": "" },
+            { "floor": 4, "eventType": "SetFilter", "filter": "VHS", "enabled": true,
+              "intensity": 100, "duration": 0, "ease": "Linear", "disableOthers": true,
+              "angleOffset": 0, "eventTag": "" },
+            { "floor": 5, "eventType": "Bloom", "enabled": false, "angleOffset": 0 },
+        ]),
+    );
+    level.parse().unwrap();
+    assert_eq!(to_value(&level)["actions"][1]["filter"], "SomeNewFilter");
+    let beats1 = level.tiles[1].data.beats().unwrap();
+    let mut effects_at = |beats: f64| -> ScreenEffects {
+        level.update(level.tempo_map().beats2seconds(beats1 + beats)).unwrap();
+        level.screen_effects().clone()
+    };
+
+    let effects = effects_at(-0.5);
+    assert!(effects.filters().is_empty());
+    assert!(effects.foreground_flash().is_none());
+
+    let effects = effects_at(1.0);
+    assert_eq!(effects.filter(&Filter::Grayscale).unwrap().intensity(), 40.0);
+    let other = Filter::Other("SomeNewFilter".to_string());
+    assert_eq!(effects.filter(&other).unwrap().properties().as_deref(), Some("{}"));
+    assert_eq!(*effects.foreground_flash(), Some(rgb::Rgba::new(128, 128, 128, 128)));
+    assert!(effects.background_flash().is_none());
+    let bloom = effects.bloom().unwrap();
+    assert_eq!((*bloom.threshold(), *bloom.intensity()), (40.0, 200.0));
+    assert!(*effects.hall_of_mirrors());
+
+    let effects = effects_at(2.5);
+    assert_eq!(effects.filter(&Filter::Grayscale).unwrap().intensity(), 80.0);
+    assert!(effects.foreground_flash().is_none());
+
+    let effects = effects_at(3.0);
+    assert_eq!(effects.filters().len(), 1);
+    assert_eq!(effects.filters()[0].filter(), &Filter::Vhs);
+    assert!(effects_at(4.0).bloom().is_none());
+}
+
+#[test]
+fn screen_tiles_and_scrolls() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 1, "eventType": "ScreenTile", "tile": [3, 2], "duration": 2,
+              "ease": "Linear", "angleOffset": 0 },
+            { "floor": 1, "eventType": "ScreenScroll", "scroll": [1, 0], "angleOffset": 0 },
+            { "floor": 3, "eventType": "ScreenScroll", "scroll": [0, -2], "angleOffset": 0,
+              "eventTag": "" },
+        ]),
+    );
+    level.parse().unwrap();
+    let seconds = |floor: usize| level.tiles[floor].data.seconds().unwrap();
+    let (seconds1, seconds3) = (seconds(1), seconds(3));
+    let mut effects_at = |seconds: f64| -> ScreenEffects {
+        level.update(seconds).unwrap();
+        level.screen_effects().clone()
+    };
+
+    let effects = effects_at(seconds1 - 0.1);
+    assert!(close(*effects.tiling(), 1.0, 1.0));
+    assert!(close(*effects.scroll_offset(), 0.0, 0.0));
+
+    let effects = effects_at((seconds1 + seconds3) / 2.0);
+    assert!(close(*effects.tiling(), 2.0, 1.5));
+    assert!(close(*effects.scroll_velocity(), 1.0, 0.0));
+    assert!(close(*effects.scroll_offset(), (seconds3 - seconds1) / 2.0, 0.0));
+
+    let effects = effects_at(seconds3 + 1.0);
+    assert!(close(*effects.tiling(), 3.0, 2.0));
+    assert!(close(*effects.scroll_velocity(), 0.0, -2.0));
+    assert!(close(*effects.scroll_offset(), seconds3 - seconds1, -2.0));
+}
+
+#[test]
+fn background_follows_custom_background() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 2, "eventType": "CustomBackground", "color": "ff0000", "bgImage": "bg.png",
+              "imageColor": "ffffff80", "parallax": [50, 0], "bgDisplayMode": "Tiled",
+              "imageSmoothing": false, "lockRot": true, "loopBG": true, "unscaledSize": 200,
+              "angleOffset": 0, "eventTag": "" },
+        ]),
+    );
+    level.settings.background_color = rgb::Rgba::new(0x10, 0x20, 0x30, 0xff);
+    level.parse().unwrap();
+    let seconds2 = level.tiles[2].data.seconds().unwrap();
+
+    level.update(seconds2 - 0.1).unwrap();
+    let background = level.background();
+    assert_eq!(background.color().now, Some(rgb::Rgba::new(0x10, 0x20, 0x30, 0xff)));
+    assert_eq!(background.image().now.as_deref(), Some(""));
+    assert_eq!(background.display_mode().now, Some(BgDisplayMode::FitToScreen));
+
+    level.update(seconds2).unwrap();
+    let background = level.background();
+    assert_eq!(background.color().now, Some(rgb::Rgba::new(0xff, 0, 0, 0xff)));
+    assert_eq!(background.image().now.as_deref(), Some("bg.png"));
+    assert_eq!(background.image_color().now, Some(rgb::Rgba::new(0xff, 0xff, 0xff, 0x80)));
+    assert_eq!(background.parallax().now, Some(vector2d::Vector2D::new(50.0, 0.0)));
+    assert_eq!(background.display_mode().now, Some(BgDisplayMode::Tiled));
+    assert_eq!(background.image_smoothing().now, Some(false));
+    assert_eq!(background.lock_rot().now, Some(true));
+    assert_eq!(background.loop_bg().now, Some(true));
+    assert_eq!(background.scaling_ratio().now, Some(200.0));
+    assert_eq!(to_value(&level)["actions"][0]["scalingRatio"], 200.0);
+}
+
+#[test]
+fn three_planets() {
+    use serde_json_lenient::json;
+    let mut level = basic_level_with(
+        json!([0, 0, 0, 0, 0, 0, 0, 0]),
+        json!([
+            { "floor": 2, "eventType": "MultiPlanet", "planets": "ThreePlanets" },
+            { "floor": 5, "eventType": "MultiPlanet", "planets": "TwoPlanets" },
+        ]),
+    );
+    level.parse().unwrap();
+    let beats = |floor: usize| level.tiles[floor].data.beats().unwrap();
+    assert!((beats(2) - beats(1) - 1.0).abs() < 1e-9);
+    assert!((beats(3) - beats(2) - 2.0 / 3.0).abs() < 1e-9);
+    assert!((beats(5) - beats(4) - 2.0 / 3.0).abs() < 1e-9);
+    assert!((beats(6) - beats(5) - 1.0).abs() < 1e-9);
+    let pivots: Vec<usize> = level.tiles.iter().map(|tile| tile.data.pivot().unwrap()).collect();
+    assert_eq!(pivots[..7], [0, 1, 0, 1, 2, 0, 1]);
+
+    let seconds3 = level.tiles[3].data.seconds().unwrap();
+    level.update(seconds3).unwrap();
+    let positions = level.planets_positions(3, seconds3).unwrap();
+    assert_eq!(positions.len(), 3);
+    let (sin, cos) = 120f64.to_radians().sin_cos();
+    assert!(close(positions[0], 2.0, 0.0));
+    assert!(close(positions[1], 3.0, 0.0));
+    assert!(close(positions[2], 3.0 + cos, sin));
+    // Two thirds of a beat later the green planet lands on the next tile.
+    let seconds4 = level.tiles[4].data.seconds().unwrap();
+    let positions = level.planets_positions(3, seconds4).unwrap();
+    assert!(close(positions[2], 4.0, 0.0));
+    let (red, blue) = level.planets_position(3, seconds4).unwrap();
+    assert!(close(blue, 3.0, 0.0));
+    assert!(close(red, 3.0 - cos, sin));
+
+    assert_eq!(level.planets_positions(6, level.tiles[6].data.seconds().unwrap()).unwrap().len(), 2);
+}